@@ -120,10 +120,18 @@ function mcfly_initialize {
     fi
     # Get the command and set the bash text to it, and move the cursor to the end of the line.
     local MCFLY_COMMAND
-    MCFLY_COMMAND=$(command awk 'NR==2{$1=a; print substr($0, 2)}' "$MCFLY_OUTPUT")
+    # McFly escapes backslashes and newlines in the commandline, so decode them with %b.
+    printf -v MCFLY_COMMAND '%b' "$(command awk 'NR==2{$1=a; print substr($0, 2)}' "$MCFLY_OUTPUT")"
     READLINE_LINE=$MCFLY_COMMAND
     READLINE_POINT=${#READLINE_LINE}
 
+    # Change to the directory McFly asked for, if any, like the commandline.
+    local MCFLY_DIR
+    printf -v MCFLY_DIR '%b' "$(command sed -n 's/^cd //p' "$MCFLY_OUTPUT")"
+    if [[ -n $MCFLY_DIR ]]; then
+      builtin cd -- "$MCFLY_DIR" || true
+    fi
+
     # Get the mode and bind the accept-line key if the mode is run.
     local MCFLY_MODE
     MCFLY_MODE=$(command awk 'NR==1{$1=a; print substr($0, 2)}' "$MCFLY_OUTPUT")
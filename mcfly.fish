@@ -54,15 +54,20 @@ if test "$__MCFLY_LOADED" != "loaded"
       set -l mcfly_output (mktemp "$tmpdir/mcfly.output.XXXXXXXX")
       eval $__MCFLY_CMD search -o '$mcfly_output' -- (commandline | string escape)
 
-      # Interpret commandline/run requests from McFly
-      set -l mode; set -l commandline
+      # Interpret commandline/run requests from McFly. Backslashes and newlines in values are
+      # escaped, so decode them with %b.
+      set -l mode; set -l commandline; set -l dir
       while read key val
         test "$key" = "mode"; and set mode "$val"
-        test "$key" = "commandline"; and set commandline "$val"
-        test "$key" = "delete"; and history delete --exact --case-sensitive "$val"
+        test "$key" = "cd"; and set dir (printf '%b' "$val" | string collect)
+        test "$key" = "commandline"; and set commandline (printf '%b' "$val" | string collect)
+        test "$key" = "delete"; and history delete --exact --case-sensitive (printf '%b' "$val" | string collect)
       end < "$mcfly_output"
       rm -f $mcfly_output
 
+      if test -n "$dir"
+        cd $dir
+      end
       if test -n "$commandline"
         commandline "$commandline"
       end
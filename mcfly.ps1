@@ -28,6 +28,22 @@ $null = New-Module mcfly {
     $env:MCFLY_HISTORY = New-TemporaryFile
     Get-Content $env:HISTFILE | Select-Object -Last 100 | Set-Content $env:MCFLY_HISTORY
 
+    <#
+    .SYNOPSIS
+    Undo the escaping of a value in McFly's output, where backslashes and newlines are written
+    as \\ and \n.
+
+    .PARAMETER Value
+    The escaped value
+    #>
+    function ConvertFrom-McFlyEscaped {
+        Param([string]$Value)
+        [regex]::Replace($Value, '\\(.)', {
+            param($match)
+            if ("n" -ceq $match.Groups[1].Value) { "`n" } else { $match.Groups[1].Value }
+        })
+    }
+
     <#
     .SYNOPSIS
     Cmdlet to run McFly
@@ -43,15 +59,25 @@ $null = New-Module mcfly {
         $lastExitTmp = $LASTEXITCODE
         $tempFile = New-TemporaryFile
         Start-Process -FilePath '::MCFLY::' -ArgumentList "search", "$CommandToComplete", -o, "$tempFile" -NoNewWindow -Wait
+        # Reset these so that variables of the same name in the caller's scope aren't used.
+        $mode = $null
+        $directory = $null
+        $commandline = $null
         foreach($line in Get-Content $tempFile) {
             $key, $value = $line -split ' ', 2
             if ("mode" -eq $key) {
                 $mode = $value
             }
+            if ("cd" -eq $key) {
+                $directory = ConvertFrom-McFlyEscaped $value
+            }
             if ("commandline" -eq $key) {
-                $commandline = $value
+                $commandline = ConvertFrom-McFlyEscaped $value
             }
         }
+        if(-not ($null -eq $directory)) {
+            Set-Location -LiteralPath $directory
+        }
         if(-not ($null -eq $commandline)) {
             [Microsoft.PowerShell.PSConsoleReadLine]::DeleteLine()
             [Microsoft.PowerShell.PSConsoleReadline]::Insert($commandline)
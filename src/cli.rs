@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use crate::time::parse_duration;
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use regex::Regex;
 use std::path::PathBuf;
 
@@ -58,6 +59,10 @@ pub enum SubCommand {
         /// The previous directory the user was in before running the command (default $OLDPWD)
         #[arg(value_name = "PATH", short, long = "old-dir")]
         old_directory: Option<String>,
+
+        /// Report whether the command would be recorded, and why not, without recording it
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Search the history
@@ -96,6 +101,86 @@ pub enum SubCommand {
         new_dir_path: String,
     },
 
+    /// Pin a command so that it always surfaces at the top of search results; lists pinned commands when no command is given
+    Pin {
+        /// The command to pin
+        command: Vec<String>,
+
+        /// Only pin the command in the given directory
+        #[arg(value_name = "PATH", short, long = "dir")]
+        directory: Option<String>,
+    },
+
+    /// Unpin a command
+    Unpin {
+        /// The command to unpin
+        #[arg(required = true)]
+        command: Vec<String>,
+
+        /// Only unpin the command from the given directory (default everywhere it is pinned)
+        #[arg(value_name = "PATH", short, long = "dir")]
+        directory: Option<String>,
+    },
+
+    /// Attach a note to a command, which is shown beside it and matched by searches; prints the current note when no note is given
+    Annotate {
+        /// The command to annotate
+        #[arg(required = true)]
+        command: Vec<String>,
+
+        /// The note, such as "rotates staging certs #deploy". An empty note removes it
+        #[arg(value_name = "NOTE", short, long)]
+        note: Option<String>,
+    },
+
+    /// Manage deleted commands, which stay in the trash for a while so that they can be restored
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+
+    /// Permanently delete runs of commands from the history. Runs selected by any of the criteria
+    /// are deleted, except for runs of pinned commands.
+    #[command(group(ArgGroup::new("criteria").required(true).multiple(true)))]
+    Prune {
+        /// Delete commands that the ignore rules would keep from being recorded now
+        #[arg(long, group = "criteria")]
+        apply_ignore_rules: bool,
+
+        /// Delete runs older than this, such as 90d, 2y or 1y6mo
+        #[arg(long, group = "criteria", value_name = "AGE", value_parser = parse_duration)]
+        older_than: Option<i64>,
+
+        /// Keep the most recent N runs. On its own, deletes all the others
+        #[arg(long, group = "criteria", value_name = "N")]
+        keep_last: Option<u64>,
+
+        /// Delete runs that repeat the previous command in the same session
+        #[arg(long, group = "criteria")]
+        dedupe_consecutive: bool,
+
+        /// Only delete runs that failed. On its own, deletes every failed run
+        #[arg(long, group = "criteria")]
+        failed_only: bool,
+
+        /// Show what would be deleted, without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Find secrets, such as tokens and passwords, in the stored history
+    Scan {
+        /// Replace the secrets found with <REDACTED>
+        #[arg(long)]
+        redact: bool,
+    },
+
+    /// Show or change settings in config.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
     /// Train the suggestion engine (developer tool)
     Train {
         /// Directory where command was run
@@ -188,6 +273,51 @@ pub enum SubCommand {
     },
 }
 
+#[derive(Subcommand)]
+pub enum TrashAction {
+    /// List deleted commands, most recently deleted first
+    List,
+
+    /// Restore a deleted command to McFly's history, and to the shell's history file for bash and
+    /// zsh
+    Restore {
+        /// The command to restore
+        #[arg(required_unless_present = "all")]
+        command: Vec<String>,
+
+        /// Restore everything in the trash
+        #[arg(long, conflicts_with = "command")]
+        all: bool,
+    },
+
+    /// Permanently delete everything in the trash
+    Empty,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Show the effective value of every setting, and where it came from
+    Show,
+
+    /// Print the effective value of a setting
+    Get {
+        /// The setting, as named in config.toml
+        key: String,
+    },
+
+    /// Set a setting in config.toml
+    Set {
+        /// The setting, as named in config.toml
+        key: String,
+
+        /// The new value
+        value: String,
+    },
+
+    /// Print the path of config.toml
+    Path,
+}
+
 #[derive(Clone, Copy, ValueEnum, Default)]
 pub enum HistoryFormat {
     #[default]
@@ -227,6 +357,22 @@ impl Cli {
     pub fn is_init(&self) -> bool {
         matches!(self.command, SubCommand::Init { .. })
     }
+
+    #[must_use]
+    pub fn is_config(&self) -> bool {
+        matches!(self.command, SubCommand::Config { .. })
+    }
+
+    /// The directory given to `add` or `search`, if any.
+    #[must_use]
+    pub fn directory(&self) -> Option<&str> {
+        match &self.command {
+            SubCommand::Add { directory, .. } | SubCommand::Search { directory, .. } => {
+                directory.as_deref()
+            }
+            _ => None,
+        }
+    }
 }
 
 impl SortOrder {
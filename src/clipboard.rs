@@ -0,0 +1,58 @@
+use std::io::{self, Write};
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// The OSC 52 escape sequence that asks the terminal to put `text` on the system clipboard.
+/// Terminals handle this themselves, so it also works over SSH. (tmux needs `set-clipboard on`.)
+fn osc52_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64_encode(text.as_bytes()))
+}
+
+/// Copy `text` to the system clipboard.
+pub fn copy<W: Write>(screen: &mut W, text: &str) -> io::Result<()> {
+    screen.write_all(osc52_sequence(text).as_bytes())?;
+    screen.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64_encode, osc52_sequence};
+
+    #[test]
+    fn base64_encode_pads() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(
+            base64_encode("ls | wc -l ⏎".as_bytes()),
+            "bHMgfCB3YyAtbCDij44="
+        );
+    }
+
+    #[test]
+    fn osc52_sequence_wraps_base64() {
+        assert_eq!(osc52_sequence("ls"), "\x1b]52;c;bHM=\x07");
+    }
+}
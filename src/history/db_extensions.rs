@@ -38,4 +38,13 @@ pub fn add_db_functions(db: &Connection) {
         },
     )
     .unwrap_or_else(|err| panic!("McFly error: Successful create_scalar_function ({err})"));
+
+    // SQLite's LOWER() and LIKE only fold ASCII characters.
+    db.create_scalar_function(
+        "unicode_lower",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| Ok(ctx.get::<String>(0)?.to_lowercase()),
+    )
+    .unwrap_or_else(|err| panic!("McFly error: Successful create_scalar_function ({err})"));
 }
@@ -1,17 +1,23 @@
 #![allow(clippy::module_inception)]
 use crate::cli::SortOrder;
 use crate::history::{db_extensions, schema};
+use crate::ignore_rules::{self, IgnoreRule};
 use crate::network::Network;
 use crate::path_update_helpers;
-use crate::settings::{HistoryFormat, ResultFilter, ResultSort, Settings, TimeRange};
+use crate::redaction::Redactor;
+use crate::settings::{
+    CaseMode, FailureFilter, HistoryFormat, ResultFilter, ResultSort, RetentionPolicy, Settings,
+    TimeRange,
+};
 use crate::shell_history;
 use crate::simplified_command::SimplifiedCommand;
 use crate::time::to_datetime;
 use itertools::Itertools;
 use rusqlite::named_params;
 use rusqlite::types::ToSql;
-use rusqlite::{Connection, MappedRows, Row};
+use rusqlite::{Connection, MappedRows, OptionalExtension, Row};
 use serde::{Serialize, Serializer};
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::io::Write;
 use std::path::PathBuf;
@@ -46,6 +52,47 @@ pub struct Command {
     pub dir: Option<String>,
     pub features: Features,
     pub match_indices: Vec<usize>,
+    /// Number of distinct commands sharing this command's template, when results are grouped.
+    pub variants: i64,
+    /// Whether the command is pinned, globally or in the current directory.
+    pub pinned: bool,
+    /// The note attached to the command with `mcfly annotate`, if any.
+    pub note: Option<String>,
+    /// Number of times the command was run.
+    pub occurrences: i64,
+    /// Whether the command was run in the current session.
+    pub in_session: bool,
+}
+
+/// A command in the trash, with every run of it that was deleted.
+#[derive(Debug, Clone)]
+pub struct DeletedCommand {
+    pub cmd: String,
+    /// The number of runs deleted.
+    pub count: i64,
+    /// When the command was last deleted.
+    pub deleted_at: i64,
+    /// When the command was last run.
+    pub last_run: i64,
+}
+
+/// A command pinned with `mcfly pin`, optionally only for a single directory.
+#[derive(Debug, Clone)]
+pub struct PinnedCommand {
+    pub cmd: String,
+    pub dir: Option<String>,
+}
+
+/// Everything recorded about a single command string, for display in the preview pane.
+#[derive(Debug, Clone, Default)]
+pub struct CommandDetails {
+    pub cmd: String,
+    /// Total number of times the command was run.
+    pub count: i64,
+    /// Directories the command was run in, with run counts, most common first.
+    pub dirs: Vec<(Option<String>, i64)>,
+    /// The most recent runs of the command, newest first.
+    pub runs: Vec<Command>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -75,63 +122,94 @@ where
     serializer.serialize_str(&to_datetime(*when_run))
 }
 
+enum MatchGrouping<'a> {
+    /// One result per distinct command.
+    None,
+    /// One result per command template.
+    ByTemplate,
+    /// Only results with the given command template.
+    Template(&'a str),
+}
+
+/// What `History::prune` deleted, or would have deleted.
+#[derive(Debug, Clone, Default)]
+pub struct PruneReport {
+    /// The number of runs.
+    pub runs: usize,
+    /// The commands whose runs were deleted, with the number of runs of each.
+    pub commands: Vec<(String, usize)>,
+}
+
+/// How often, in seconds, the automatic retention policy is applied.
+const RETENTION_INTERVAL: i64 = 24 * 60 * 60;
+
 #[derive(Debug)]
 pub struct History {
     pub connection: Connection,
     pub network: Network,
+    /// Masks secrets in the commands that are added, when redaction is on.
+    pub redactor: Option<Redactor>,
 }
 
-const IGNORED_COMMANDS: [&str; 7] = [
-    "pwd",
-    "ls",
-    "cd",
-    "cd ..",
-    "clear",
-    "history",
-    "mcfly search",
-];
-
 impl History {
+    /// Open the history database, importing the shell's history into a new one if there isn't
+    /// one yet. Commands added through it are masked with `redactor`.
     #[must_use]
-    pub fn load(history_format: HistoryFormat) -> History {
+    pub fn load(
+        history_format: HistoryFormat,
+        ignore_rules: &[IgnoreRule],
+        redactor: Option<&Redactor>,
+    ) -> History {
         let db_path = Settings::mcfly_db_path();
-        let history = if db_path.exists() {
+        let mut history = if db_path.exists() {
             History::from_db_path(db_path)
         } else {
-            History::from_shell_history(history_format)
+            History::from_shell_history(history_format, ignore_rules, redactor)
         };
+        history.redactor = redactor.cloned();
         schema::migrate(&history.connection);
         history
     }
 
-    pub fn should_add(&self, command: &str) -> bool {
+    /// `command` with its secrets masked, when redaction is on.
+    fn redacted<'c>(&self, command: &'c str) -> Cow<'c, str> {
+        self.redactor
+            .as_ref()
+            .and_then(|redactor| redactor.redact(command))
+            .map_or(Cow::Borrowed(command), Cow::Owned)
+    }
+
+    /// Why `command`, run in `dir`, shouldn't be recorded, or `None` if it should be.
+    pub fn ignore_reason(
+        &self,
+        command: &str,
+        dir: &str,
+        ignore_rules: &[IgnoreRule],
+    ) -> Option<String> {
         // Ignore empty commands.
         if command.is_empty() {
-            return false;
+            return Some(String::from("it is empty"));
         }
 
         // Ignore commands added via a ctrl-r search.
         if command.starts_with("#mcfly:") {
-            return false;
+            return Some(String::from("it was added by a McFly search"));
         }
 
         // Ignore commands with a leading space.
         if command.starts_with(' ') {
-            return false;
+            return Some(String::from("it starts with a space"));
         }
 
-        // Ignore blacklisted commands.
-        if IGNORED_COMMANDS.contains(&command) {
-            return false;
+        if let Some(rule) = ignore_rules::matching_rule(ignore_rules, command, Some(dir)) {
+            return Some(format!("it matches the ignore rule {rule}"));
         }
 
         // Ignore the previous command (independent of Session ID) so that opening a new terminal
         // window won't replay the last command in the history.
-        let last_command = self.last_command(&None);
-        if last_command.is_none() {
-            return true;
-        }
-        !command.eq(&last_command.unwrap().cmd)
+        self.last_command(&None)
+            .filter(|last_command| last_command.cmd == command)
+            .map(|_| String::from("it is the same as the previous command"))
     }
 
     pub fn add(
@@ -143,6 +221,7 @@ impl History {
         exit_code: Option<i32>,
         old_dir: &Option<String>,
     ) {
+        let command: &str = &self.redacted(command);
         self.possibly_update_paths(command, exit_code);
         let selected = self.determine_if_selected_from_ui(command, session_id, dir);
         let simplified_command = SimplifiedCommand::new(command, true);
@@ -191,6 +270,7 @@ impl History {
     }
 
     pub fn record_selected_from_ui(&self, command: &str, session_id: &str, dir: &str) {
+        let command: &str = &self.redacted(command);
         self.connection.execute("INSERT INTO selected_commands (cmd, session_id, dir) VALUES (:cmd, :session_id, :dir)",
                                       &[
                                           (":cmd", &command.to_owned()),
@@ -252,130 +332,249 @@ impl History {
         cmd: &str,
         num: i16,
         fuzzy: i16,
+        case_mode: &CaseMode,
         result_sort: &ResultSort,
     ) -> Vec<Command> {
-        let (wildcard, match_function, cmd) = if Self::is_case_sensitive(cmd) {
-            // escape '*' with '[*]' and replace '%' with '*' for glob matching
-            ("*", "GLOB", cmd.replace("*", "[*]").replace("%", "*"))
-        } else {
-            ("%", "LIKE", cmd.to_string())
-        };
+        self.query_matches(
+            cmd,
+            num,
+            fuzzy,
+            case_mode,
+            result_sort,
+            &MatchGrouping::None,
+        )
+    }
 
-        let mut like_query = wildcard.to_string();
+    /// Like `find_matches`, but returns only the best-ranked command for each command template,
+    /// with `variants` set to the number of distinct matching commands sharing that template.
+    pub fn find_grouped_matches(
+        &self,
+        cmd: &str,
+        num: i16,
+        fuzzy: i16,
+        case_mode: &CaseMode,
+        result_sort: &ResultSort,
+    ) -> Vec<Command> {
+        self.query_matches(
+            cmd,
+            num,
+            fuzzy,
+            case_mode,
+            result_sort,
+            &MatchGrouping::ByTemplate,
+        )
+    }
 
-        if fuzzy > 0 {
-            like_query.push_str(&cmd.split("").collect::<Vec<&str>>().join(wildcard));
+    /// Returns the matching commands that share the given command template, in rank order.
+    pub fn find_template_variants(
+        &self,
+        cmd_tpl: &str,
+        cmd: &str,
+        num: i16,
+        fuzzy: i16,
+        case_mode: &CaseMode,
+        result_sort: &ResultSort,
+    ) -> Vec<Command> {
+        self.query_matches(
+            cmd,
+            num,
+            fuzzy,
+            case_mode,
+            result_sort,
+            &MatchGrouping::Template(cmd_tpl),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn query_matches(
+        &self,
+        cmd: &str,
+        num: i16,
+        fuzzy: i16,
+        case_mode: &CaseMode,
+        result_sort: &ResultSort,
+        grouping: &MatchGrouping,
+    ) -> Vec<Command> {
+        // Both paths use GLOB so that '%' is the only wildcard, whatever the case mode.
+        let case_sensitive = Self::is_case_sensitive(cmd, case_mode);
+        let (matched_column, note_column, like_query) = if case_sensitive {
+            ("cmd", "note", Self::glob_pattern(cmd, fuzzy > 0))
         } else {
-            like_query.push_str(&cmd);
-        }
+            (
+                "unicode_lower(cmd)",
+                "unicode_lower(IFNULL(note, ''))",
+                Self::glob_pattern(&cmd.to_lowercase(), fuzzy > 0),
+            )
+        };
 
-        like_query += wildcard;
+        let matching = format!("({matched_column} GLOB (:like) OR {note_column} GLOB (:like))");
+        // When grouping by template, each group is shown as its best-ranked command, and the sort
+        // columns are aggregated over the whole group. Pinned commands are never folded into a
+        // group.
+        let (source, variants, last_run, last_run_here, occurrences, frecency) = match grouping {
+            MatchGrouping::None | MatchGrouping::Template(_) => {
+                let template_clause = if matches!(grouping, MatchGrouping::Template(_)) {
+                    " AND cmd_tpl = :cmd_tpl"
+                } else {
+                    ""
+                };
+                (
+                    format!("contextual_commands WHERE {matching}{template_clause}"),
+                    "1",
+                    "last_run",
+                    "last_run_here",
+                    "occurrences",
+                    "frecency",
+                )
+            }
+            MatchGrouping::ByTemplate => (
+                String::from(
+                    "(SELECT *, ROW_NUMBER() OVER (PARTITION BY group_key
+                            ORDER BY rank DESC, last_run DESC, id DESC) AS group_row
+                        FROM matching)
+                    JOIN (SELECT group_key, COUNT(*) AS variants,
+                            MAX(last_run) AS group_last_run,
+                            MAX(last_run_here) AS group_last_run_here,
+                            SUM(occurrences) AS group_occurrences,
+                            SUM(frecency) AS group_frecency
+                        FROM matching GROUP BY group_key) USING (group_key)
+                    WHERE group_row = 1",
+                ),
+                "variants",
+                "group_last_run",
+                "group_last_run_here",
+                "group_occurrences",
+                "group_frecency",
+            ),
+        };
+        let with_matching = if matches!(grouping, MatchGrouping::ByTemplate) {
+            format!(
+                "WITH matching AS (
+                    SELECT *, CASE WHEN pinned THEN cmd ELSE cmd_tpl END AS group_key
+                    FROM contextual_commands WHERE {matching}
+                )"
+            )
+        } else {
+            String::new()
+        };
 
-        let order_by_column: &str = match &result_sort {
-            ResultSort::LastRun => "last_run",
-            _ => "rank",
+        let order_by = match &result_sort {
+            ResultSort::Rank => String::from("rank DESC"),
+            ResultSort::LastRun => format!("{last_run} DESC"),
+            ResultSort::Frequency => format!("{occurrences} DESC, rank DESC"),
+            ResultSort::Frecency => format!("{frecency} DESC, rank DESC"),
+            ResultSort::RecentHere => format!("{last_run_here} DESC, {last_run} DESC"),
         };
 
         let query: &str = &format!(
-            "{} {} {} {} {}",
-            "SELECT id, cmd, cmd_tpl, session_id, when_run, exit_code, selected, dir, rank,
+            "{with_matching}
+            SELECT id, cmd, cmd_tpl, session_id, when_run, exit_code, selected, dir, rank,
                 age_factor, length_factor, exit_factor, recent_failure_factor,
                 selected_dir_factor, dir_factor, overlap_factor, immediate_overlap_factor,
-                selected_occurrences_factor, occurrences_factor, last_run
-            FROM contextual_commands
-            WHERE cmd",
-            match_function,
-            "(:like)
-            ORDER BY",
-            order_by_column,
-            "DESC LIMIT :limit"
+                selected_occurrences_factor, occurrences_factor, {last_run},
+                {variants}, pinned, note, {occurrences}, in_session
+            FROM {source}
+            ORDER BY pinned DESC, {order_by} LIMIT :limit"
         )[..];
 
+        let mut params: Vec<(&str, &dyn ToSql)> = vec![(":like", &like_query), (":limit", &num)];
+        if let MatchGrouping::Template(cmd_tpl) = grouping {
+            params.push((":cmd_tpl", cmd_tpl));
+        }
+
         let mut statement = self
             .connection
             .prepare(query)
             .unwrap_or_else(|err| panic!("McFly error: Prepare to work ({err})"));
         let command_iter = statement
-            .query_map(
-                named_params! { ":like": &like_query, ":limit": &num },
-                |row| {
-                    let text: String = row
-                        .get(1)
-                        .unwrap_or_else(|err| panic!("McFly error: cmd to be readable ({err})"));
-
-                    let bounds = Self::calc_match_indices(&text, &cmd, fuzzy);
-
-                    Ok(Command {
-                        id: row.get(0).unwrap_or_else(|err| {
-                            panic!("McFly error: id to be readable ({err})")
+            .query_map(params.as_slice(), |row| {
+                let text: String = row
+                    .get(1)
+                    .unwrap_or_else(|err| panic!("McFly error: cmd to be readable ({err})"));
+
+                let bounds = Self::calc_match_indices(&text, cmd, fuzzy, case_sensitive);
+
+                Ok(Command {
+                    id: row
+                        .get(0)
+                        .unwrap_or_else(|err| panic!("McFly error: id to be readable ({err})")),
+                    cmd: text,
+                    cmd_tpl: row.get(2).unwrap_or_else(|err| {
+                        panic!("McFly error: cmd_tpl to be readable ({err})")
+                    }),
+                    session_id: row.get(3).unwrap_or_else(|err| {
+                        panic!("McFly error: session_id to be readable ({err})")
+                    }),
+                    when_run: row.get(4).unwrap_or_else(|err| {
+                        panic!("McFly error: when_run to be readable ({err})")
+                    }),
+                    exit_code: row.get(5).unwrap_or_else(|err| {
+                        panic!("McFly error: exit_code to be readable ({err})")
+                    }),
+                    selected: row.get(6).unwrap_or_else(|err| {
+                        panic!("McFly error: selected to be readable ({err})")
+                    }),
+                    dir: row
+                        .get(7)
+                        .unwrap_or_else(|err| panic!("McFly error: dir to be readable ({err})")),
+                    rank: row
+                        .get(8)
+                        .unwrap_or_else(|err| panic!("McFly error: rank to be readable ({err})")),
+                    match_indices: bounds,
+                    features: Features {
+                        age_factor: row.get(9).unwrap_or_else(|err| {
+                            panic!("McFly error: age_factor to be readable ({err})")
                         }),
-                        cmd: text,
-                        cmd_tpl: row.get(2).unwrap_or_else(|err| {
-                            panic!("McFly error: cmd_tpl to be readable ({err})")
+                        length_factor: row.get(10).unwrap_or_else(|err| {
+                            panic!("McFly error: length_factor to be readable ({err})")
                         }),
-                        session_id: row.get(3).unwrap_or_else(|err| {
-                            panic!("McFly error: session_id to be readable ({err})")
+                        exit_factor: row.get(11).unwrap_or_else(|err| {
+                            panic!("McFly error: exit_factor to be readable ({err})")
                         }),
-                        when_run: row.get(4).unwrap_or_else(|err| {
-                            panic!("McFly error: when_run to be readable ({err})")
+                        recent_failure_factor: row.get(12).unwrap_or_else(|err| {
+                            panic!("McFly error: recent_failure_factor to be readable ({err})")
                         }),
-                        exit_code: row.get(5).unwrap_or_else(|err| {
-                            panic!("McFly error: exit_code to be readable ({err})")
+                        selected_dir_factor: row.get(13).unwrap_or_else(|err| {
+                            panic!("McFly error: selected_dir_factor to be readable ({err})")
                         }),
-                        selected: row.get(6).unwrap_or_else(|err| {
-                            panic!("McFly error: selected to be readable ({err})")
+                        dir_factor: row.get(14).unwrap_or_else(|err| {
+                            panic!("McFly error: dir_factor to be readable ({err})")
                         }),
-                        dir: row.get(7).unwrap_or_else(|err| {
-                            panic!("McFly error: dir to be readable ({err})")
+                        overlap_factor: row.get(15).unwrap_or_else(|err| {
+                            panic!("McFly error: overlap_factor to be readable ({err})")
                         }),
-                        rank: row.get(8).unwrap_or_else(|err| {
-                            panic!("McFly error: rank to be readable ({err})")
+                        immediate_overlap_factor: row.get(16).unwrap_or_else(|err| {
+                            panic!("McFly error: immediate_overlap_factor to be readable ({err})")
                         }),
-                        match_indices: bounds,
-                        features: Features {
-                            age_factor: row.get(9).unwrap_or_else(|err| {
-                                panic!("McFly error: age_factor to be readable ({err})")
-                            }),
-                            length_factor: row.get(10).unwrap_or_else(|err| {
-                                panic!("McFly error: length_factor to be readable ({err})")
-                            }),
-                            exit_factor: row.get(11).unwrap_or_else(|err| {
-                                panic!("McFly error: exit_factor to be readable ({err})")
-                            }),
-                            recent_failure_factor: row.get(12).unwrap_or_else(|err| {
-                                panic!(
-                                    "McFly error: recent_failure_factor to be readable ({err})"
-                                )
-                            }),
-                            selected_dir_factor: row.get(13).unwrap_or_else(|err| {
-                                panic!("McFly error: selected_dir_factor to be readable ({err})")
-                            }),
-                            dir_factor: row.get(14).unwrap_or_else(|err| {
-                                panic!("McFly error: dir_factor to be readable ({err})")
-                            }),
-                            overlap_factor: row.get(15).unwrap_or_else(|err| {
-                                panic!("McFly error: overlap_factor to be readable ({err})")
-                            }),
-                            immediate_overlap_factor: row.get(16).unwrap_or_else(|err| {
-                                panic!(
-                                    "McFly error: immediate_overlap_factor to be readable ({err})"
-                                )
-                            }),
-                            selected_occurrences_factor: row.get(17).unwrap_or_else(|err| {
-                                panic!(
-                                    "McFly error: selected_occurrences_factor to be readable ({err})"
-                                )
-                            }),
-                            occurrences_factor: row.get(18).unwrap_or_else(|err| {
-                                panic!("McFly error: occurrences_factor to be readable ({err})")
-                            }),
-                        },
-                        last_run: row.get(19).unwrap_or_else(|err| {
-                            panic!("McFly error: last_run to be readable ({err})")
+                        selected_occurrences_factor: row.get(17).unwrap_or_else(|err| {
+                            panic!(
+                                "McFly error: selected_occurrences_factor to be readable ({err})"
+                            )
                         }),
-                    })
-                },
-            )
+                        occurrences_factor: row.get(18).unwrap_or_else(|err| {
+                            panic!("McFly error: occurrences_factor to be readable ({err})")
+                        }),
+                    },
+                    last_run: row.get(19).unwrap_or_else(|err| {
+                        panic!("McFly error: last_run to be readable ({err})")
+                    }),
+                    variants: row.get(20).unwrap_or_else(|err| {
+                        panic!("McFly error: variants to be readable ({err})")
+                    }),
+                    pinned: row
+                        .get(21)
+                        .unwrap_or_else(|err| panic!("McFly error: pinned to be readable ({err})")),
+                    note: row
+                        .get(22)
+                        .unwrap_or_else(|err| panic!("McFly error: note to be readable ({err})")),
+                    occurrences: row.get(23).unwrap_or_else(|err| {
+                        panic!("McFly error: occurrences to be readable ({err})")
+                    }),
+                    in_session: row.get(24).unwrap_or_else(|err| {
+                        panic!("McFly error: in_session to be readable ({err})")
+                    }),
+                })
+            })
             .unwrap_or_else(|err| panic!("McFly error: Query Map to work ({err})"));
 
         let mut names = Vec::new();
@@ -385,10 +584,15 @@ impl History {
             }));
         }
 
-        if fuzzy > 0 && result_sort != &ResultSort::LastRun {
+        if fuzzy > 0 && result_sort == &ResultSort::Rank {
             names = names
                 .into_iter()
                 .sorted_unstable_by(|a, b| {
+                    // Pinned commands stay on top.
+                    if a.pinned != b.pinned {
+                        return b.pinned.cmp(&a.pinned);
+                    }
+
                     // Fuzzy matches impose new ordering criteria on top of the
                     // natural rank-based sorting: at the most basic level,
                     // shorter and earlier matches are more likely to be
@@ -432,23 +636,85 @@ impl History {
         names
     }
 
-    /// Enable case sensitivity when input string contains uppercase
-    fn is_case_sensitive(cmd: &str) -> bool {
-        cmd.chars().any(|c| c.is_uppercase())
+    /// In smart mode, enable case sensitivity when input string contains uppercase
+    fn is_case_sensitive(cmd: &str, case_mode: &CaseMode) -> bool {
+        match case_mode {
+            CaseMode::Smart => cmd.chars().any(char::is_uppercase),
+            CaseMode::Sensitive => true,
+            CaseMode::Insensitive => false,
+        }
+    }
+
+    /// Build a GLOB pattern that finds `cmd` anywhere in a command. '%' matches any number of
+    /// characters; every other character, including GLOB's own wildcards, matches itself.
+    fn glob_pattern(cmd: &str, fuzzy: bool) -> String {
+        let mut pattern = String::from("*");
+        for c in cmd.chars() {
+            match c {
+                '%' => pattern.push('*'),
+                '*' | '?' | '[' => {
+                    pattern.push('[');
+                    pattern.push(c);
+                    pattern.push(']');
+                }
+                _ => pattern.push(c),
+            }
+            if fuzzy {
+                pattern.push('*');
+            }
+        }
+        if !fuzzy {
+            pattern.push('*');
+        }
+        pattern
+    }
+
+    /// Escape LIKE's wildcards in `text`, for use with `ESCAPE '\\'`.
+    fn like_escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+
+    /// Lowercase `text` one character at a time, returning the lowercased string along with the
+    /// byte offset in `text` of each byte in it, since lowercasing can change a character's length.
+    fn lowercase_with_offsets(text: &str) -> (String, Vec<usize>) {
+        let mut lowercased = String::with_capacity(text.len());
+        let mut offsets = Vec::with_capacity(text.len());
+        for (i, c) in text.char_indices() {
+            for lower in c.to_lowercase() {
+                lowercased.push(lower);
+                offsets.resize(lowercased.len(), i);
+            }
+        }
+        (lowercased, offsets)
     }
 
     /// Calculate the indices of the matches in the text.
-    fn calc_match_indices(text: &str, cmd: &str, fuzzy: i16) -> Vec<usize> {
-        let (text, cmd) = if Self::is_case_sensitive(cmd) {
-            (text.to_string(), cmd.to_string())
-        } else {
-            (text.to_lowercase(), cmd.to_lowercase())
-        };
+    fn calc_match_indices(text: &str, cmd: &str, fuzzy: i16, case_sensitive: bool) -> Vec<usize> {
+        if case_sensitive {
+            return Self::calc_exact_match_indices(text, cmd, fuzzy);
+        }
+
+        let (lowercased, offsets) = Self::lowercase_with_offsets(text);
+        Self::calc_exact_match_indices(&lowercased, &cmd.to_lowercase(), fuzzy)
+            .into_iter()
+            .map(|i| offsets[i])
+            .dedup()
+            .collect()
+    }
 
+    fn calc_exact_match_indices(text: &str, cmd: &str, fuzzy: i16) -> Vec<usize> {
         match fuzzy {
             0 => text
                 .match_indices(&cmd)
                 .flat_map(|(index, _)| index..index + cmd.len())
+                .filter(|&index| text.is_char_boundary(index))
                 .collect(),
             _ => {
                 let mut search_iter = cmd.chars().peekable();
@@ -475,6 +741,7 @@ impl History {
         &self,
         dir: &str,
         result_filter: &ResultFilter,
+        failure_filter: &FailureFilter,
         session_id: &Option<String>,
         start_time: Option<i64>,
         end_time: Option<i64>,
@@ -554,14 +821,20 @@ impl History {
             0
         };
 
-        let dir_filter_off = match &result_filter {
-            ResultFilter::Global => true,
-            ResultFilter::CurrentDirectory => false,
-        };
+        let dir_filter_off = *result_filter != ResultFilter::CurrentDirectory;
+        let session_filter_off = *result_filter != ResultFilter::CurrentSession;
+        let exit_filter_off = *result_filter != ResultFilter::Successful;
+        let directory_pattern = Self::like_escape(dir);
+        let subdirectories = format!("{}/%", Self::like_escape(dir.trim_end_matches('/')));
+        let hide_failures = *failure_filter == FailureFilter::Hide;
+        let only_failures = *failure_filter == FailureFilter::Only;
 
         self.connection.execute(
             "CREATE TEMP TABLE contextual_commands AS SELECT
-                  id, cmd, cmd_tpl, session_id, when_run, MAX(when_run) AS last_run, exit_code, selected, dir,
+                  id, cmd, cmd_tpl, session_id, when_run, MAX(when_run) AS last_run, selected, dir,
+
+                  /* exit code of the last run */
+                  (SELECT exit_code FROM commands c2 WHERE c2.cmd = c.cmd ORDER BY when_run DESC, id DESC LIMIT 1) AS exit_code,
 
                   /* to be filled in later */
                   0.0 AS rank,
@@ -597,17 +870,50 @@ impl History {
                   SUM(CASE WHEN selected = 1 THEN 1.0 ELSE 0.0 END) / :max_selected_occurrences AS selected_occurrences_factor,
 
                   /* percentage of time this command is run relative to the most common command (1: this is the most common command, 0: this is the least common command) */
-                  COUNT(*) / :max_occurrences AS occurrences_factor
+                  COUNT(*) / :max_occurrences AS occurrences_factor,
+
+                  /* raw number of times this command was run */
+                  COUNT(*) AS occurrences,
+
+                  /* frecency: each run counts for more the more recent it was (within an hour, a day, a week, or older) */
+                  SUM(CASE WHEN :now - when_run < 3600 THEN 4.0
+                           WHEN :now - when_run < 86400 THEN 2.0
+                           WHEN :now - when_run < 604800 THEN 0.5
+                           ELSE 0.25 END) AS frecency,
+
+                  /* last time this command was run in this directory (NULL if never) */
+                  MAX(CASE WHEN dir = :directory THEN when_run ELSE NULL END) AS last_run_here,
+
+                  /* pinned globally or in this directory */
+                  EXISTS(SELECT 1 FROM pinned_commands p WHERE p.cmd = c.cmd AND (p.dir IS NULL OR p.dir = :directory)) AS pinned,
+
+                  /* note from mcfly annotate (NULL if none) */
+                  (SELECT note FROM annotations a WHERE a.cmd = c.cmd) AS note,
+
+                  /* 1 if run in the current session, 0 if not */
+                  MAX(CASE WHEN session_id = :session_id THEN 1 ELSE 0 END) AS in_session
 
                   FROM commands c
-                  WHERE id > :min_id AND when_run > :start_time AND when_run < :end_time AND (:dir_filter_off OR dir LIKE :directory)
+                  WHERE id > :min_id AND when_run > :start_time AND when_run < :end_time
+                    AND (:dir_filter_off OR dir LIKE :directory_pattern ESCAPE '\\'
+                         OR dir LIKE :subdirectories ESCAPE '\\')
+                    AND (:session_filter_off OR session_id = :session_id)
+                    AND (:exit_filter_off OR exit_code = 0)
                   GROUP BY cmd
+                  HAVING (NOT :hide_failures OR exit_factor > 0) AND (NOT :only_failures OR exit_factor = 0)
                   ORDER BY id DESC;",
             named_params! {
                 ":when_run_max": &when_run_max,
                 ":history_duration": &(when_run_max - when_run_min),
                 ":directory": &dir.to_owned(),
                 ":dir_filter_off": &dir_filter_off,
+                ":directory_pattern": &directory_pattern,
+                ":subdirectories": &subdirectories,
+                ":session_filter_off": &session_filter_off,
+                ":session_id": &session_id.clone().unwrap_or_default(),
+                ":exit_filter_off": &exit_filter_off,
+                ":hide_failures": &hide_failures,
+                ":only_failures": &only_failures,
                 ":max_occurrences": &max_occurrences,
                 ":max_length": &max_length,
                 ":max_selected_occurrences": &max_selected_occurrences,
@@ -622,6 +928,33 @@ impl History {
                 ":min_id": &min_id,
             }).unwrap_or_else(|err| panic!("McFly error: Creation of temp table to work ({err})"));
 
+        // Pinned commands always surface when searching, even if they haven't been run or have
+        // been filtered out. (The trainer looks back in time, and only wants commands that ran.)
+        if start_time.is_none() && end_time.is_none() {
+            self.connection
+                .execute(
+                    "INSERT INTO contextual_commands (id, cmd, cmd_tpl, session_id, when_run, last_run,
+                        exit_code, selected, dir, rank, length_factor, age_factor, exit_factor,
+                        recent_failure_factor, dir_factor, selected_dir_factor, overlap_factor,
+                        immediate_overlap_factor, selected_occurrences_factor, occurrences_factor,
+                        occurrences, frecency, last_run_here, pinned, note, in_session)
+                     SELECT -MIN(p.id), p.cmd, p.cmd_tpl, '', NULL, NULL, NULL, 0, p.dir, 0.0,
+                        LENGTH(p.cmd) / :max_length, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+                        0, 0.0, NULL, 1, (SELECT note FROM annotations a WHERE a.cmd = p.cmd), 0
+                     FROM pinned_commands p
+                     WHERE (p.dir IS NULL OR p.dir = :directory)
+                       AND p.cmd NOT IN (SELECT cmd FROM contextual_commands)
+                     GROUP BY p.cmd;",
+                    named_params! {
+                        ":directory": &dir.to_owned(),
+                        ":max_length": &max_length,
+                    },
+                )
+                .unwrap_or_else(|err| {
+                    panic!("McFly error: Insert of pinned commands into temp table to work ({err})")
+                });
+        }
+
         self.connection
             .execute(
                 "UPDATE contextual_commands
@@ -707,6 +1040,48 @@ impl History {
         vec
     }
 
+    pub fn command_details(&self, cmd: &str, num_runs: i16) -> CommandDetails {
+        let count: i64 = self
+            .connection
+            .query_row(
+                "SELECT COUNT(*) FROM commands WHERE cmd = :cmd",
+                &[(":cmd", &cmd)],
+                |row| row.get(0),
+            )
+            .unwrap_or_else(|err| panic!("McFly error: Query to work ({err})"));
+
+        let dirs = self.run_query(
+            "SELECT dir, COUNT(*) AS c FROM commands WHERE cmd = :cmd GROUP BY dir ORDER BY c DESC",
+            &[(":cmd", &cmd)],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        );
+
+        let runs = self.run_query(
+            "SELECT id, cmd, cmd_tpl, session_id, when_run, exit_code, selected, dir FROM commands WHERE cmd = :cmd ORDER BY id DESC LIMIT :limit",
+            &[(":cmd", &cmd), (":limit", &num_runs)],
+            |row| {
+                Ok(Command {
+                    id: row.get(0)?,
+                    cmd: row.get(1)?,
+                    cmd_tpl: row.get(2)?,
+                    session_id: row.get(3)?,
+                    when_run: row.get(4)?,
+                    exit_code: row.get(5)?,
+                    selected: row.get(6)?,
+                    dir: row.get(7)?,
+                    ..Command::default()
+                })
+            },
+        );
+
+        CommandDetails {
+            cmd: cmd.to_string(),
+            count,
+            dirs,
+            runs,
+        }
+    }
+
     pub fn last_command(&self, session_id: &Option<String>) -> Option<Command> {
         self.commands(session_id, 1, 0, false).first().cloned()
     }
@@ -723,8 +1098,132 @@ impl History {
             .collect()
     }
 
-    pub fn delete_command(&self, command: &str) {
+    /// Pin `command`, either everywhere or only in `dir`.
+    pub fn pin(&self, command: &str, dir: Option<&str>) {
+        let simplified_command = SimplifiedCommand::new(command, true);
         self.connection
+            .execute(
+                "INSERT OR IGNORE INTO pinned_commands (cmd, cmd_tpl, dir, when_pinned) \
+                 VALUES (:cmd, :cmd_tpl, :dir, strftime('%s','now'))",
+                named_params! {
+                    ":cmd": &command,
+                    ":cmd_tpl": &simplified_command.result,
+                    ":dir": &dir,
+                },
+            )
+            .unwrap_or_else(|err| {
+                panic!("McFly error: Insert into pinned_commands to work ({err})")
+            });
+    }
+
+    /// Unpin `command` in `dir`, or everywhere it is pinned when `dir` is `None`. Returns the
+    /// number of pins removed.
+    pub fn unpin(&self, command: &str, dir: Option<&str>) -> usize {
+        let deleted = match dir {
+            Some(dir) => self.connection.execute(
+                "DELETE FROM pinned_commands WHERE cmd = :cmd AND dir = :dir",
+                named_params! { ":cmd": &command, ":dir": &dir },
+            ),
+            None => self.connection.execute(
+                "DELETE FROM pinned_commands WHERE cmd = :cmd",
+                named_params! { ":cmd": &command },
+            ),
+        };
+        deleted.unwrap_or_else(|err| {
+            panic!("McFly error: DELETE from pinned_commands to work ({err})")
+        })
+    }
+
+    /// Unpin `command` globally and in `dir`, which are the pins that surface it there, leaving
+    /// any pins in other directories. Returns the number of pins removed.
+    pub fn unpin_here(&self, command: &str, dir: &str) -> usize {
+        self.connection
+            .execute(
+                "DELETE FROM pinned_commands WHERE cmd = :cmd AND (dir IS NULL OR dir = :dir)",
+                named_params! { ":cmd": &command, ":dir": &dir },
+            )
+            .unwrap_or_else(|err| {
+                panic!("McFly error: DELETE from pinned_commands to work ({err})")
+            })
+    }
+
+    /// Whether `command` is pinned anywhere at all.
+    pub fn is_pinned(&self, command: &str) -> bool {
+        self.connection
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM pinned_commands WHERE cmd = :cmd)",
+                named_params! { ":cmd": &command },
+                |row| row.get(0),
+            )
+            .unwrap_or_else(|err| panic!("McFly error: Query to work ({err})"))
+    }
+
+    pub fn pinned_commands(&self) -> Vec<PinnedCommand> {
+        self.run_query(
+            "SELECT cmd, dir FROM pinned_commands ORDER BY dir IS NOT NULL, dir, when_pinned",
+            &[],
+            |row| {
+                Ok(PinnedCommand {
+                    cmd: row.get(0)?,
+                    dir: row.get(1)?,
+                })
+            },
+        )
+    }
+
+    /// Attach `note` to `command`, replacing any existing note. An empty note removes it.
+    pub fn annotate(&self, command: &str, note: &str) {
+        let note = note.trim();
+        if note.is_empty() {
+            self.connection
+                .execute(
+                    "DELETE FROM annotations WHERE cmd = :cmd",
+                    named_params! { ":cmd": &command },
+                )
+                .unwrap_or_else(|err| {
+                    panic!("McFly error: DELETE from annotations to work ({err})")
+                });
+        } else {
+            self.connection
+                .execute(
+                    "INSERT OR REPLACE INTO annotations (cmd, note, when_annotated) \
+                     VALUES (:cmd, :note, strftime('%s','now'))",
+                    named_params! { ":cmd": &command, ":note": &note },
+                )
+                .unwrap_or_else(|err| {
+                    panic!("McFly error: Insert into annotations to work ({err})")
+                });
+        }
+    }
+
+    pub fn annotation(&self, command: &str) -> Option<String> {
+        self.connection
+            .query_row(
+                "SELECT note FROM annotations WHERE cmd = :cmd",
+                named_params! { ":cmd": &command },
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap_or_else(|err| panic!("McFly error: Query to work ({err})"))
+    }
+
+    /// Move every run of `command`, and its selections in the interface, to the trash, from
+    /// where they can be restored until they are purged.
+    pub fn delete_command(&self, command: &str) {
+        let transaction = self
+            .connection
+            .unchecked_transaction()
+            .unwrap_or_else(|err| panic!("McFly error: Unable to begin transaction ({err})"));
+        transaction
+            .execute(
+                "INSERT OR REPLACE INTO deleted_selected_commands (id, cmd, session_id, dir) \
+                 SELECT id, cmd, session_id, dir FROM selected_commands WHERE cmd = :command",
+                &[(":command", &command)],
+            )
+            .unwrap_or_else(|err| {
+                panic!("McFly error: Insert into deleted_selected_commands to work ({err})")
+            });
+        transaction
             .execute(
                 "DELETE FROM selected_commands WHERE cmd = :command",
                 &[(":command", &command)],
@@ -732,13 +1231,363 @@ impl History {
             .unwrap_or_else(|err| {
                 panic!("McFly error: DELETE from selected_commands to work ({err})")
             });
-
-        self.connection
+        transaction
+            .execute(
+                "INSERT OR REPLACE INTO deleted_commands (id, cmd, cmd_tpl, session_id, when_run, exit_code, selected, dir, old_dir, deleted_at) \
+                 SELECT id, cmd, cmd_tpl, session_id, when_run, exit_code, selected, dir, old_dir, strftime('%s','now') \
+                 FROM commands WHERE cmd = :command",
+                &[(":command", &command)],
+            )
+            .unwrap_or_else(|err| panic!("McFly error: Insert into deleted_commands to work ({err})"));
+        transaction
             .execute(
                 "DELETE FROM commands WHERE cmd = :command",
                 &[(":command", &command)],
             )
             .unwrap_or_else(|err| panic!("McFly error: DELETE from commands to work ({err})"));
+        transaction
+            .commit()
+            .unwrap_or_else(|err| panic!("McFly error: Unable to commit transaction: ({err})"));
+    }
+
+    /// Permanently delete the runs selected by `policy`, then clean up `selected_commands`. With
+    /// `dry_run`, nothing is changed and the report says what would have been deleted. The
+    /// database isn't compacted; see `vacuum`.
+    pub fn prune(
+        &self,
+        policy: &RetentionPolicy,
+        ignore_rules: &[IgnoreRule],
+        dry_run: bool,
+    ) -> PruneReport {
+        let mut criteria = Vec::new();
+        let mut params: Vec<(&str, &dyn ToSql)> = Vec::new();
+        let cutoff = policy.older_than.map(|age| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_else(|err| panic!("McFly error: Time went backwards ({err})"))
+                .as_secs() as i64
+                - age
+        });
+        if let Some(cutoff) = &cutoff {
+            criteria.push("when_run < :cutoff");
+            params.push((":cutoff", cutoff));
+        }
+        if policy.dedupe_consecutive {
+            // Keep the last run of each streak, so that the command keeps its last run time.
+            criteria.push(
+                "id IN (SELECT id FROM (SELECT id, cmd, LEAD(cmd) OVER \
+                 (PARTITION BY session_id ORDER BY when_run, id) AS next_cmd FROM commands) \
+                 WHERE cmd = next_cmd)",
+            );
+        }
+        // `failed_only` and `keep_last` only narrow the other criteria down, so on their own they
+        // start from every run.
+        if criteria.is_empty()
+            && !policy.apply_ignore_rules
+            && (policy.failed_only || policy.keep_last.is_some())
+        {
+            criteria.push("1");
+        }
+
+        let mut filters = String::from(" AND cmd NOT IN (SELECT cmd FROM pinned_commands)");
+        if policy.failed_only {
+            filters.push_str(" AND exit_code != 0");
+        }
+        let keep_last = policy
+            .keep_last
+            .map(|n| i64::try_from(n).unwrap_or(i64::MAX));
+        if keep_last.is_some() {
+            filters.push_str(
+                " AND id NOT IN (SELECT id FROM commands ORDER BY when_run DESC, id DESC \
+                 LIMIT :keep_last)",
+            );
+        }
+        let mut filter_params: Vec<(&str, &dyn ToSql)> = Vec::new();
+        if let Some(keep_last) = &keep_last {
+            filter_params.push((":keep_last", keep_last));
+        }
+
+        let mut runs: Vec<(i64, String)> = Vec::new();
+        if !criteria.is_empty() {
+            params.extend(filter_params.iter().copied());
+            runs = self.run_query(
+                &format!(
+                    "SELECT id, cmd FROM commands WHERE ({}){filters}",
+                    criteria.join(" OR ")
+                ),
+                &params,
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            );
+        }
+        if policy.apply_ignore_rules {
+            let ignored: Vec<(i64, String, Option<String>)> = self.run_query(
+                &format!("SELECT id, cmd, dir FROM commands WHERE 1{filters}"),
+                &filter_params,
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            );
+            runs.extend(
+                ignored
+                    .into_iter()
+                    .filter(|(_, cmd, dir)| {
+                        ignore_rules::matching_rule(ignore_rules, cmd, dir.as_deref()).is_some()
+                    })
+                    .map(|(id, cmd, _)| (id, cmd)),
+            );
+        }
+        runs.sort_unstable();
+        runs.dedup();
+
+        let mut commands: Vec<(String, usize)> = Vec::new();
+        for (_, cmd) in runs.iter().sorted_by(|a, b| a.1.cmp(&b.1)) {
+            match commands.last_mut() {
+                Some((last, count)) if last == cmd => *count += 1,
+                _ => commands.push((cmd.clone(), 1)),
+            }
+        }
+
+        let report = PruneReport {
+            runs: runs.len(),
+            commands,
+        };
+        if dry_run || runs.is_empty() {
+            return report;
+        }
+
+        let transaction = self
+            .connection
+            .unchecked_transaction()
+            .unwrap_or_else(|err| panic!("McFly error: Unable to begin transaction ({err})"));
+        {
+            let mut statement = transaction
+                .prepare("DELETE FROM commands WHERE id = :id")
+                .unwrap_or_else(|err| panic!("McFly error: Unable to prepare delete ({err})"));
+            for (id, _) in &runs {
+                statement
+                    .execute(named_params! { ":id": id })
+                    .unwrap_or_else(|err| {
+                        panic!("McFly error: DELETE from commands to work ({err})")
+                    });
+            }
+        }
+        transaction
+            .execute(
+                "DELETE FROM selected_commands WHERE cmd NOT IN (SELECT cmd FROM commands)",
+                [],
+            )
+            .unwrap_or_else(|err| {
+                panic!("McFly error: DELETE from selected_commands to work ({err})")
+            });
+        transaction
+            .commit()
+            .unwrap_or_else(|err| panic!("McFly error: Unable to commit transaction: ({err})"));
+        report
+    }
+
+    /// Compact the database, returning how many bytes it got smaller. This rewrites the whole
+    /// file, so it's left to `mcfly prune` rather than done with the automatic retention.
+    pub fn vacuum(&self) -> u64 {
+        let size_before = self.database_size();
+        self.connection
+            .execute_batch("VACUUM")
+            .unwrap_or_else(|err| panic!("McFly error: VACUUM to work ({err})"));
+        size_before.saturating_sub(self.database_size())
+    }
+
+    /// Prune with `policy` if it hasn't been done in the last day, without compacting the
+    /// database. Returns `None` if the policy is empty or it was done recently, and otherwise
+    /// the report, whose `runs` is 0 if nothing was deleted.
+    pub fn apply_retention(
+        &self,
+        policy: &RetentionPolicy,
+        ignore_rules: &[IgnoreRule],
+    ) -> Option<PruneReport> {
+        if policy.is_empty() {
+            return None;
+        }
+        let last_run: Option<i64> = self
+            .connection
+            .query_row("SELECT MAX(when_run) FROM retention_runs", [], |row| {
+                row.get(0)
+            })
+            .unwrap_or_else(|err| panic!("McFly error: Query to work ({err})"));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_else(|err| panic!("McFly error: Time went backwards ({err})"))
+            .as_secs() as i64;
+        if last_run.is_some_and(|last_run| now - last_run < RETENTION_INTERVAL) {
+            return None;
+        }
+
+        let report = self.prune(policy, ignore_rules, false);
+        self.connection
+            .execute(
+                "INSERT INTO retention_runs (when_run, runs_deleted) VALUES (:when_run, :runs_deleted)",
+                named_params! { ":when_run": &now, ":runs_deleted": &(report.runs as i64) },
+            )
+            .unwrap_or_else(|err| panic!("McFly error: Insert into retention_runs to work ({err})"));
+        Some(report)
+    }
+
+    /// The size of the database, in bytes.
+    fn database_size(&self) -> u64 {
+        let size: i64 = self
+            .connection
+            .query_row(
+                "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap_or_else(|err| panic!("McFly error: Query to work ({err})"));
+        u64::try_from(size).unwrap_or_default()
+    }
+
+    /// The distinct commands in the history and the trash, with the number of runs of each.
+    pub fn stored_commands(&self) -> Vec<(String, i64)> {
+        self.run_query(
+            "SELECT cmd, COUNT(*) FROM \
+             (SELECT cmd FROM commands UNION ALL SELECT cmd FROM deleted_commands) \
+             GROUP BY cmd ORDER BY cmd",
+            &[],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+    }
+
+    /// Replace `command` with `redacted` everywhere it is stored, including the trash, pins and
+    /// notes.
+    pub fn redact_command(&self, command: &str, redacted: &str) {
+        let cmd_tpl = SimplifiedCommand::new(redacted, true).result;
+        let transaction = self
+            .connection
+            .unchecked_transaction()
+            .unwrap_or_else(|err| panic!("McFly error: Unable to begin transaction ({err})"));
+        let execute = |statement: &str, params: &[(&str, &dyn ToSql)]| {
+            transaction
+                .execute(statement, params)
+                .unwrap_or_else(|err| panic!("McFly error: Redacting commands to work ({err})"));
+        };
+        let with_template = named_params! {
+            ":command": &command,
+            ":redacted": &redacted,
+            ":cmd_tpl": &cmd_tpl,
+        };
+        let without_template = named_params! { ":command": &command, ":redacted": &redacted };
+
+        execute(
+            "UPDATE commands SET cmd = :redacted, cmd_tpl = :cmd_tpl WHERE cmd = :command",
+            with_template,
+        );
+        execute(
+            "UPDATE deleted_commands SET cmd = :redacted, cmd_tpl = :cmd_tpl WHERE cmd = :command",
+            with_template,
+        );
+        execute(
+            "UPDATE selected_commands SET cmd = :redacted WHERE cmd = :command",
+            without_template,
+        );
+        execute(
+            "UPDATE deleted_selected_commands SET cmd = :redacted WHERE cmd = :command",
+            without_template,
+        );
+        // Pins and notes are unique per command, and the redacted command may have its own.
+        execute(
+            "UPDATE OR IGNORE pinned_commands SET cmd = :redacted, cmd_tpl = :cmd_tpl \
+             WHERE cmd = :command",
+            with_template,
+        );
+        execute(
+            "UPDATE OR IGNORE annotations SET cmd = :redacted WHERE cmd = :command",
+            without_template,
+        );
+        let command_only = named_params! { ":command": &command };
+        execute(
+            "DELETE FROM pinned_commands WHERE cmd = :command",
+            command_only,
+        );
+        execute("DELETE FROM annotations WHERE cmd = :command", command_only);
+        transaction
+            .commit()
+            .unwrap_or_else(|err| panic!("McFly error: Unable to commit transaction: ({err})"));
+    }
+
+    /// Move `command` out of the trash and back into the history, or everything in the trash
+    /// when `command` is `None`. Returns the number of runs restored.
+    pub fn restore_command(&self, command: Option<&str>) -> usize {
+        let transaction = self
+            .connection
+            .unchecked_transaction()
+            .unwrap_or_else(|err| panic!("McFly error: Unable to begin transaction ({err})"));
+        let restored = transaction
+            .execute(
+                "INSERT OR IGNORE INTO commands (id, cmd, cmd_tpl, session_id, when_run, exit_code, selected, dir, old_dir) \
+                 SELECT id, cmd, cmd_tpl, session_id, when_run, exit_code, selected, dir, old_dir \
+                 FROM deleted_commands WHERE :command IS NULL OR cmd = :command",
+                named_params! { ":command": &command },
+            )
+            .unwrap_or_else(|err| panic!("McFly error: Insert into commands to work ({err})"));
+        transaction
+            .execute(
+                "INSERT OR IGNORE INTO selected_commands (id, cmd, session_id, dir) \
+                 SELECT id, cmd, session_id, dir \
+                 FROM deleted_selected_commands WHERE :command IS NULL OR cmd = :command",
+                named_params! { ":command": &command },
+            )
+            .unwrap_or_else(|err| {
+                panic!("McFly error: Insert into selected_commands to work ({err})")
+            });
+        for table in ["deleted_commands", "deleted_selected_commands"] {
+            transaction
+                .execute(
+                    &format!("DELETE FROM {table} WHERE :command IS NULL OR cmd = :command"),
+                    named_params! { ":command": &command },
+                )
+                .unwrap_or_else(|err| panic!("McFly error: DELETE from {table} to work ({err})"));
+        }
+        transaction
+            .commit()
+            .unwrap_or_else(|err| panic!("McFly error: Unable to commit transaction: ({err})"));
+        restored
+    }
+
+    /// The commands in the trash, most recently deleted first.
+    pub fn deleted_commands(&self) -> Vec<DeletedCommand> {
+        self.run_query(
+            "SELECT cmd, COUNT(*), MAX(deleted_at) AS last_deleted_at, MAX(when_run) \
+             FROM deleted_commands GROUP BY cmd ORDER BY last_deleted_at DESC",
+            &[],
+            |row| {
+                Ok(DeletedCommand {
+                    cmd: row.get(0)?,
+                    count: row.get(1)?,
+                    deleted_at: row.get(2)?,
+                    last_run: row.get(3)?,
+                })
+            },
+        )
+    }
+
+    /// Permanently delete commands that have been in the trash for at least `age` seconds, or
+    /// everything in the trash when `age` is `None`. Returns the number of runs purged.
+    pub fn purge_deleted_commands(&self, age: Option<i64>) -> usize {
+        let purged = self
+            .connection
+            .execute(
+                "DELETE FROM deleted_commands \
+                 WHERE :age IS NULL OR deleted_at <= strftime('%s','now') - :age",
+                named_params! { ":age": &age },
+            )
+            .unwrap_or_else(|err| {
+                panic!("McFly error: DELETE from deleted_commands to work ({err})")
+            });
+        self.connection
+            .execute(
+                "DELETE FROM deleted_selected_commands \
+                 WHERE cmd NOT IN (SELECT cmd FROM deleted_commands)",
+                [],
+            )
+            .unwrap_or_else(|err| {
+                panic!("McFly error: DELETE from deleted_selected_commands to work ({err})")
+            });
+        purged
     }
 
     pub fn update_paths(&self, old_path: &str, new_path: &str, print_output: bool) {
@@ -822,7 +1671,11 @@ impl History {
         })
     }
 
-    fn from_shell_history(history_format: HistoryFormat) -> History {
+    fn from_shell_history(
+        history_format: HistoryFormat,
+        ignore_rules: &[IgnoreRule],
+        redactor: Option<&Redactor>,
+    ) -> History {
         print!(
             "McFly: Importing shell history for the first time. This may take a minute or two..."
         );
@@ -847,30 +1700,7 @@ impl History {
 
         db_extensions::add_db_functions(&connection);
 
-        connection.execute_batch(
-            "CREATE TABLE commands( \
-                      id INTEGER PRIMARY KEY AUTOINCREMENT, \
-                      cmd TEXT NOT NULL, \
-                      cmd_tpl TEXT, \
-                      session_id TEXT NOT NULL, \
-                      when_run INTEGER NOT NULL, \
-                      exit_code INTEGER NOT NULL, \
-                      selected INTEGER NOT NULL, \
-                      dir TEXT, \
-                      old_dir TEXT \
-                  ); \
-                  CREATE INDEX command_cmds ON commands (cmd);\
-                  CREATE INDEX command_session_id ON commands (session_id);\
-                  CREATE INDEX command_dirs ON commands (dir);\
-                  \
-                  CREATE TABLE selected_commands( \
-                      id INTEGER PRIMARY KEY AUTOINCREMENT, \
-                      cmd TEXT NOT NULL, \
-                      session_id TEXT NOT NULL, \
-                      dir TEXT NOT NULL \
-                  ); \
-                  CREATE INDEX selected_command_session_cmds ON selected_commands (session_id, cmd);"
-        ).unwrap_or_else(|err| panic!("McFly error: Unable to initialize history db ({err})"));
+        History::create_tables(&connection);
 
         let transaction = connection
             .transaction()
@@ -880,12 +1710,15 @@ impl History {
                 .prepare("INSERT INTO commands (cmd, cmd_tpl, session_id, when_run, exit_code, selected) VALUES (:cmd, :cmd_tpl, :session_id, :when_run, :exit_code, :selected)")
                 .unwrap_or_else(|err| panic!("McFly error: Unable to prepare insert ({err})"));
             for command in commands {
-                if !IGNORED_COMMANDS.contains(&command.command.as_str()) {
-                    let simplified_command = SimplifiedCommand::new(&command.command, true);
-                    if !command.command.is_empty()
+                if ignore_rules::matching_rule(ignore_rules, &command.command, None).is_none() {
+                    let cmd = redactor
+                        .and_then(|redactor| redactor.redact(&command.command))
+                        .unwrap_or(command.command);
+                    let simplified_command = SimplifiedCommand::new(&cmd, true);
+                    if !cmd.is_empty()
                         && !simplified_command.result.is_empty()
                         && let Err(e) = statement.execute(named_params! {
-                            ":cmd": &command.command,
+                            ":cmd": &cmd,
                             ":cmd_tpl": &simplified_command.result.clone(),
                             ":session_id": &"IMPORTED",
                             ":when_run": &command.when,
@@ -895,7 +1728,7 @@ impl History {
                     {
                         println!(
                             "A single history line could not be saved due to '{}' (command was '{}'), but other inserts should be fine.",
-                            e, &command.command
+                            e, &cmd
                         );
                     }
                 }
@@ -912,9 +1745,81 @@ impl History {
         History {
             connection,
             network: Network::default(),
+            redactor: None,
         }
     }
 
+    /// Create the tables of a new database, at the current schema version.
+    fn create_tables(connection: &Connection) {
+        connection.execute_batch(
+            "CREATE TABLE commands( \
+                      id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                      cmd TEXT NOT NULL, \
+                      cmd_tpl TEXT, \
+                      session_id TEXT NOT NULL, \
+                      when_run INTEGER NOT NULL, \
+                      exit_code INTEGER NOT NULL, \
+                      selected INTEGER NOT NULL, \
+                      dir TEXT, \
+                      old_dir TEXT \
+                  ); \
+                  CREATE INDEX command_cmds ON commands (cmd);\
+                  CREATE INDEX command_session_id ON commands (session_id);\
+                  CREATE INDEX command_dirs ON commands (dir);\
+                  \
+                  CREATE TABLE selected_commands( \
+                      id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                      cmd TEXT NOT NULL, \
+                      session_id TEXT NOT NULL, \
+                      dir TEXT NOT NULL \
+                  ); \
+                  CREATE INDEX selected_command_session_cmds ON selected_commands (session_id, cmd);\
+                  \
+                  CREATE TABLE pinned_commands( \
+                      id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                      cmd TEXT NOT NULL, \
+                      cmd_tpl TEXT NOT NULL, \
+                      dir TEXT, \
+                      when_pinned INTEGER NOT NULL \
+                  ); \
+                  CREATE UNIQUE INDEX pinned_command_cmd_dirs ON pinned_commands (cmd, IFNULL(dir, ''));\
+                  \
+                  CREATE TABLE annotations( \
+                      cmd TEXT PRIMARY KEY, \
+                      note TEXT NOT NULL, \
+                      when_annotated INTEGER NOT NULL \
+                  ); \
+                  \
+                  CREATE TABLE deleted_commands( \
+                      id INTEGER PRIMARY KEY, \
+                      cmd TEXT NOT NULL, \
+                      cmd_tpl TEXT, \
+                      session_id TEXT NOT NULL, \
+                      when_run INTEGER NOT NULL, \
+                      exit_code INTEGER NOT NULL, \
+                      selected INTEGER NOT NULL, \
+                      dir TEXT, \
+                      old_dir TEXT, \
+                      deleted_at INTEGER NOT NULL \
+                  ); \
+                  CREATE INDEX deleted_command_cmds ON deleted_commands (cmd);\
+                  \
+                  CREATE TABLE deleted_selected_commands( \
+                      id INTEGER PRIMARY KEY, \
+                      cmd TEXT NOT NULL, \
+                      session_id TEXT NOT NULL, \
+                      dir TEXT NOT NULL \
+                  ); \
+                  CREATE INDEX deleted_selected_command_cmds ON deleted_selected_commands (cmd);\
+                  \
+                  CREATE TABLE retention_runs( \
+                      id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                      when_run INTEGER NOT NULL, \
+                      runs_deleted INTEGER NOT NULL \
+                  );"
+        ).unwrap_or_else(|err| panic!("McFly error: Unable to initialize history db ({err})"));
+    }
+
     fn from_db_path(path: PathBuf) -> History {
         let connection = Connection::open(path)
             .unwrap_or_else(|err| panic!("McFly error: Unable to open history database ({err})"));
@@ -922,6 +1827,548 @@ impl History {
         History {
             connection,
             network: Network::default(),
+            redactor: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use crate::history::db_extensions;
+    use crate::ignore_rules::IgnoreRule;
+    use crate::network::Network;
+    use crate::redaction::Redactor;
+    use crate::settings::{CaseMode, FailureFilter, ResultFilter, ResultSort, RetentionPolicy};
+    use rusqlite::{Connection, named_params};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn history() -> History {
+        let connection = Connection::open_in_memory().unwrap();
+        db_extensions::add_db_functions(&connection);
+        History::create_tables(&connection);
+        History {
+            connection,
+            network: Network::default(),
+            redactor: None,
+        }
+    }
+
+    fn insert(
+        history: &History,
+        cmd: &str,
+        session_id: &str,
+        dir: &str,
+        when_run: i64,
+        exit_code: i32,
+    ) {
+        history
+            .connection
+            .execute(
+                "INSERT INTO commands (cmd, cmd_tpl, session_id, when_run, exit_code, selected, dir) \
+                 VALUES (:cmd, :cmd, :session_id, :when_run, :exit_code, 0, :dir)",
+                named_params! {
+                    ":cmd": cmd,
+                    ":session_id": session_id,
+                    ":when_run": when_run,
+                    ":exit_code": exit_code,
+                    ":dir": dir,
+                },
+            )
+            .unwrap();
+    }
+
+    fn results(
+        history: &History,
+        dir: &str,
+        result_filter: &ResultFilter,
+        failure_filter: &FailureFilter,
+    ) -> Vec<String> {
+        history.build_cache_table(
+            dir,
+            result_filter,
+            failure_filter,
+            &Some(String::from("s1")),
+            None,
+            None,
+            Some(1000),
+            None,
+        );
+        let mut cmds: Vec<String> = history
+            .find_matches("", 100, 0, &CaseMode::Smart, &ResultSort::Rank)
+            .into_iter()
+            .map(|command| command.cmd)
+            .collect();
+        cmds.sort();
+        cmds
+    }
+
+    #[test]
+    fn result_filters() {
+        let history = history();
+        insert(&history, "make", "s1", "/src/my_app", 100, 0);
+        insert(&history, "make test", "s1", "/src/my_app/lib", 200, 1);
+        insert(&history, "cargo run", "s2", "/src/myXapp", 300, 0);
+        insert(&history, "ls", "s2", "/src/my_app_old", 400, 0);
+        insert(&history, "false", "s1", "/tmp", 500, 1);
+        insert(&history, "make", "s2", "/other", 600, 2);
+
+        let cases = [
+            (
+                "/src/my_app",
+                ResultFilter::CurrentDirectory,
+                FailureFilter::Show,
+                vec!["make", "make test"],
+            ),
+            (
+                "/",
+                ResultFilter::CurrentSession,
+                FailureFilter::Show,
+                vec!["false", "make", "make test"],
+            ),
+            (
+                "/",
+                ResultFilter::Successful,
+                FailureFilter::Show,
+                vec!["cargo run", "ls", "make"],
+            ),
+            (
+                "/",
+                ResultFilter::Global,
+                FailureFilter::Show,
+                vec!["cargo run", "false", "ls", "make", "make test"],
+            ),
+            (
+                "/",
+                ResultFilter::Global,
+                FailureFilter::Hide,
+                vec!["cargo run", "ls", "make"],
+            ),
+            (
+                "/",
+                ResultFilter::Global,
+                FailureFilter::Only,
+                vec!["false", "make test"],
+            ),
+        ];
+        for (dir, result_filter, failure_filter, expected) in cases {
+            assert_eq!(
+                results(&history, dir, &result_filter, &failure_filter),
+                expected,
+                "{result_filter:?} {failure_filter:?}"
+            );
+        }
+
+        // The status column shows the exit code of the last run, wherever it was.
+        results(&history, "/", &ResultFilter::Global, &FailureFilter::Show);
+        let make = history
+            .find_matches("make", 10, 0, &CaseMode::Smart, &ResultSort::Rank)
+            .into_iter()
+            .find(|command| command.cmd == "make")
+            .unwrap();
+        assert_eq!((make.last_run, make.exit_code), (Some(600), Some(2)));
+    }
+
+    #[test]
+    fn match_notes() {
+        let history = history();
+        insert(
+            &history,
+            "kubectl rollout restart deploy/api",
+            "s1",
+            "/",
+            100,
+            0,
+        );
+        insert(&history, "ls", "s1", "/", 200, 0);
+        history.annotate(
+            "kubectl rollout restart deploy/api",
+            "Restart the API #deploy",
+        );
+        results(&history, "/", &ResultFilter::Global, &FailureFilter::Show);
+
+        let search = |cmd: &str| -> Vec<(String, Option<String>)> {
+            history
+                .find_matches(cmd, 100, 0, &CaseMode::Smart, &ResultSort::Rank)
+                .into_iter()
+                .map(|command| (command.cmd, command.note))
+                .collect()
+        };
+        let expected = vec![(
+            String::from("kubectl rollout restart deploy/api"),
+            Some(String::from("Restart the API #deploy")),
+        )];
+        assert_eq!(search("#deploy"), expected);
+        assert_eq!(search("the api"), expected);
+        assert_eq!(search("rollout"), expected);
+        assert_eq!(search("the API"), expected);
+        assert!(search("The api").is_empty());
+        assert!(search("nothing like this").is_empty());
+    }
+
+    #[test]
+    fn pin_commands() {
+        let history = history();
+        insert(&history, "make", "s1", "/src", 100, 0);
+        insert(&history, "ls", "s1", "/src", 200, 0);
+
+        history.pin("make", None);
+        history.pin("make", Some("/src"));
+        history.pin("make", Some("/other"));
+        history.pin("docker compose up", Some("/src"));
+        history.pin("cargo test", Some("/other"));
+        assert!(history.is_pinned("make"));
+        assert!(history.is_pinned("cargo test"));
+        assert!(!history.is_pinned("ls"));
+
+        // Pinned commands that were never run here are added to the results.
+        history.build_cache_table(
+            "/src",
+            &ResultFilter::Global,
+            &FailureFilter::Show,
+            &None,
+            None,
+            None,
+            Some(1000),
+            None,
+        );
+        let pinned: Vec<(String, bool)> = history
+            .find_matches("", 100, 0, &CaseMode::Smart, &ResultSort::Rank)
+            .into_iter()
+            .map(|command| (command.cmd, command.pinned))
+            .collect();
+        assert_eq!(pinned.len(), 3);
+        assert!(pinned.contains(&(String::from("make"), true)));
+        assert!(pinned.contains(&(String::from("docker compose up"), true)));
+        assert!(pinned.contains(&(String::from("ls"), false)));
+
+        assert_eq!(history.unpin_here("make", "/src"), 2);
+        assert!(history.is_pinned("make"));
+        assert_eq!(history.unpin("make", Some("/elsewhere")), 0);
+        assert_eq!(history.unpin("make", None), 1);
+        assert!(!history.is_pinned("make"));
+    }
+
+    fn count(history: &History, query: &str) -> i64 {
+        history
+            .connection
+            .query_row(query, [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn delete_restore_and_purge() {
+        let history = history();
+        insert(&history, "rm -rf build", "s1", "/", 100, 0);
+        insert(&history, "rm -rf build", "s1", "/", 200, 0);
+        insert(&history, "ls", "s1", "/", 300, 0);
+        history
+            .connection
+            .execute_batch(
+                "INSERT INTO selected_commands (cmd, session_id, dir) VALUES ('rm -rf build', 's1', '/')",
+            )
+            .unwrap();
+        let history_count = "SELECT COUNT(*) FROM commands WHERE cmd = 'rm -rf build'";
+        let selected_count = "SELECT COUNT(*) FROM selected_commands";
+
+        history.delete_command("rm -rf build");
+        assert_eq!(count(&history, history_count), 0);
+        assert_eq!(count(&history, selected_count), 0);
+        let deleted = history.deleted_commands();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(
+            (
+                deleted[0].cmd.as_str(),
+                deleted[0].count,
+                deleted[0].last_run
+            ),
+            ("rm -rf build", 2, 200)
+        );
+
+        assert_eq!(history.restore_command(Some("rm -rf build")), 2);
+        assert_eq!(count(&history, history_count), 2);
+        assert_eq!(count(&history, selected_count), 1);
+        assert!(history.deleted_commands().is_empty());
+
+        history.delete_command("rm -rf build");
+        history.delete_command("ls");
+        assert_eq!(history.purge_deleted_commands(Some(3600)), 0);
+        assert_eq!(history.deleted_commands().len(), 2);
+        assert_eq!(history.purge_deleted_commands(None), 3);
+        assert!(history.deleted_commands().is_empty());
+        assert_eq!(
+            count(&history, "SELECT COUNT(*) FROM deleted_selected_commands"),
+            0
+        );
+        assert_eq!(history.restore_command(None), 0);
+        assert_eq!(count(&history, "SELECT COUNT(*) FROM commands"), 0);
+    }
+
+    #[test]
+    fn ignore_reasons() {
+        let history = history();
+        insert(&history, "make", "s1", "/", 100, 0);
+        let mut deploy = IgnoreRule::parse("make deploy").unwrap();
+        deploy.dir = Some(String::from("/work"));
+        let rules = vec![
+            IgnoreRule::parse(r"regex:(?i)^token\s+\d+").unwrap(),
+            deploy,
+        ];
+        let reason = |command: &str, dir: &str| history.ignore_reason(command, dir, &rules);
+
+        assert_eq!(reason("", "/").as_deref(), Some("it is empty"));
+        assert_eq!(
+            reason(" make", "/").as_deref(),
+            Some("it starts with a space")
+        );
+        assert_eq!(
+            reason("#mcfly: make", "/").as_deref(),
+            Some("it was added by a McFly search")
+        );
+        assert_eq!(
+            reason("TOKEN 42", "/").as_deref(),
+            Some(r"it matches the ignore rule 'regex:(?i)^token\s+\d+'")
+        );
+        assert_eq!(
+            reason("make deploy", "/work/app").as_deref(),
+            Some("it matches the ignore rule 'make deploy' in /work")
+        );
+        assert_eq!(reason("make deploy", "/tmp"), None);
+        assert_eq!(
+            reason("make", "/").as_deref(),
+            Some("it is the same as the previous command")
+        );
+    }
+
+    #[test]
+    fn prune() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        const DAY: i64 = 24 * 60 * 60;
+        let setup = || {
+            let history = history();
+            insert(&history, "git status", "s1", "/", now - 1000 * DAY, 0);
+            insert(&history, "make", "s1", "/", now - 900 * DAY, 2);
+            insert(&history, "make", "s1", "/", now - 800 * DAY, 2);
+            insert(&history, "deploy", "s1", "/", now - 700 * DAY, 0);
+            insert(&history, "ls", "s2", "/", now - 10 * DAY, 1);
+            insert(&history, "ls", "s2", "/", now - 5 * DAY, 0);
+            insert(&history, "make", "s1", "/", now - DAY, 0);
+            history.pin("deploy", None);
+            for cmd in ["git status", "make", "deploy", "ls"] {
+                history.record_selected_from_ui(cmd, "s1", "/");
+            }
+            history
+        };
+        let rules = vec![
+            IgnoreRule::parse("glob:git *").unwrap(),
+            IgnoreRule::parse("ls").unwrap(),
+        ];
+        let policy = |f: fn(&mut RetentionPolicy)| {
+            let mut policy = RetentionPolicy::default();
+            f(&mut policy);
+            policy
+        };
+
+        // The ids of the runs that are left. Run 4 is pinned, so it's never deleted.
+        let cases: Vec<(RetentionPolicy, Vec<i64>)> = vec![
+            (policy(|p| p.older_than = Some(365 * DAY)), vec![4, 5, 6, 7]),
+            (policy(|p| p.keep_last = Some(2)), vec![4, 6, 7]),
+            (policy(|p| p.keep_last = Some(0)), vec![4]),
+            (
+                policy(|p| {
+                    p.older_than = Some(3 * DAY);
+                    p.keep_last = Some(2);
+                }),
+                vec![4, 6, 7],
+            ),
+            // The last run of each streak is kept.
+            (policy(|p| p.dedupe_consecutive = true), vec![1, 3, 4, 6, 7]),
+            (policy(|p| p.failed_only = true), vec![1, 4, 6, 7]),
+            (
+                policy(|p| {
+                    p.failed_only = true;
+                    p.older_than = Some(365 * DAY);
+                }),
+                vec![1, 4, 5, 6, 7],
+            ),
+            (policy(|p| p.apply_ignore_rules = true), vec![2, 3, 4, 7]),
+            (
+                policy(|p| {
+                    p.failed_only = true;
+                    p.apply_ignore_rules = true;
+                }),
+                vec![1, 2, 3, 4, 6, 7],
+            ),
+        ];
+        let ids = |history: &History| -> Vec<i64> {
+            history.run_query("SELECT id FROM commands ORDER BY id", &[], |row| row.get(0))
+        };
+        for (policy, left) in cases {
+            let history = setup();
+            let deleted = 7 - left.len();
+            let report = history.prune(&policy, &rules, true);
+            assert_eq!(report.runs, deleted, "{policy:?}");
+            assert_eq!(ids(&history).len(), 7, "{policy:?} --dry-run");
+            assert_eq!(count(&history, "SELECT COUNT(*) FROM selected_commands"), 4);
+
+            assert_eq!(history.prune(&policy, &rules, false).runs, deleted);
+            assert_eq!(ids(&history), left, "{policy:?}");
+            assert_eq!(
+                count(
+                    &history,
+                    "SELECT COUNT(*) FROM selected_commands WHERE cmd NOT IN (SELECT cmd FROM commands)"
+                ),
+                0,
+                "{policy:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn apply_retention_at_most_once_a_day() {
+        let history = history();
+        insert(&history, "ls", "s1", "/", 100, 0);
+        assert!(
+            history
+                .apply_retention(&RetentionPolicy::default(), &[])
+                .is_none()
+        );
+        let policy = RetentionPolicy {
+            keep_last: Some(5),
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(history.apply_retention(&policy, &[]).unwrap().runs, 0);
+        insert(&history, "make", "s1", "/", 200, 0);
+        let policy = RetentionPolicy {
+            keep_last: Some(0),
+            ..RetentionPolicy::default()
+        };
+        assert!(history.apply_retention(&policy, &[]).is_none());
+        assert_eq!(count(&history, "SELECT COUNT(*) FROM commands"), 2);
+    }
+
+    #[test]
+    fn grouped_sorts_aggregate_over_the_group() {
+        let history = history();
+        for (i, message) in ["a", "b", "c", "d"].iter().enumerate() {
+            insert(
+                &history,
+                &format!("git commit -m {message}"),
+                "s1",
+                "/",
+                100 + i as i64,
+                0,
+            );
+        }
+        history
+            .connection
+            .execute_batch("UPDATE commands SET cmd_tpl = 'git commit -m #'")
+            .unwrap();
+        for when_run in [200, 210, 220] {
+            insert(&history, "ls", "s1", "/", when_run, 0);
         }
+        insert(&history, "pwd", "s1", "/", 150, 0);
+        results(&history, "/", &ResultFilter::Global, &FailureFilter::Show);
+        // A group is shown as its best-ranked variant, whatever it's sorted by.
+        history
+            .connection
+            .execute_batch(
+                "UPDATE contextual_commands \
+                 SET rank = CASE cmd WHEN 'git commit -m b' THEN 10 ELSE 1 END",
+            )
+            .unwrap();
+
+        let grouped = |result_sort: &ResultSort| -> Vec<(String, Option<i64>, i64, String)> {
+            history
+                .find_grouped_matches("", 100, 0, &CaseMode::Smart, result_sort)
+                .into_iter()
+                .map(|command| {
+                    (
+                        command.cmd_tpl,
+                        command.last_run,
+                        command.occurrences,
+                        command.cmd,
+                    )
+                })
+                .collect()
+        };
+        let by_frequency = grouped(&ResultSort::Frequency);
+        assert_eq!(by_frequency[0].0, "git commit -m #");
+        assert_eq!(by_frequency[0].2, 4);
+        let by_last_run = grouped(&ResultSort::LastRun);
+        assert_eq!(
+            by_last_run
+                .iter()
+                .map(|row| row.0.as_str())
+                .collect::<Vec<_>>(),
+            vec!["ls", "pwd", "git commit -m #"]
+        );
+        assert_eq!(by_last_run[2].1, Some(103));
+        assert_eq!(by_last_run[2].3, "git commit -m b");
+        assert_eq!(by_frequency[0].3, "git commit -m b");
+    }
+
+    #[test]
+    fn glob_pattern_only_treats_percent_as_a_wildcard() {
+        assert_eq!(History::glob_pattern("git", false), "*git*");
+        assert_eq!(History::glob_pattern("a%b", false), "*a*b*");
+        assert_eq!(History::glob_pattern("ls *.rs", false), "*ls [*].rs*");
+        assert_eq!(History::glob_pattern("a?[b]", false), "*a[?][[]b]*");
+        assert_eq!(History::glob_pattern("ab", true), "*a*b*");
+    }
+
+    #[test]
+    fn calc_match_indices_folds_unicode_case() {
+        assert_eq!(
+            History::calc_match_indices("ÉCHO été", "éc", 0, false),
+            vec![0, 2]
+        );
+        assert_eq!(
+            History::calc_match_indices("İstanbul", "st", 0, false),
+            vec![2, 3]
+        );
+        assert_eq!(
+            History::calc_match_indices("echo Été", "Été", 0, true),
+            vec![5, 7, 8]
+        );
+    }
+
+    #[test]
+    fn add_masks_secrets() {
+        let mut history = history();
+        history.redactor = Some(Redactor::default());
+        history.add(
+            "curl -H 'Authorization: Bearer abc123' https://example.com",
+            "s1",
+            "/",
+            &Some(100),
+            Some(0),
+            &None,
+        );
+        history.record_selected_from_ui("export AWS_SECRET_ACCESS_KEY=abc123", "s1", "/");
+        assert_eq!(
+            count(
+                &history,
+                "SELECT COUNT(*) FROM commands WHERE cmd = 'curl -H ''Authorization: Bearer <REDACTED>'' https://example.com'"
+            ),
+            1
+        );
+        assert_eq!(
+            count(
+                &history,
+                "SELECT COUNT(*) FROM commands WHERE cmd LIKE '%abc123%'"
+            ),
+            0
+        );
+        assert_eq!(
+            count(
+                &history,
+                "SELECT COUNT(*) FROM selected_commands WHERE cmd LIKE '%abc123%'"
+            ),
+            0
+        );
     }
 }
@@ -1,4 +1,6 @@
-pub use self::history::{Command, DumpCommand, Features, History};
+pub use self::history::{
+    Command, CommandDetails, DeletedCommand, DumpCommand, Features, History, PinnedCommand,
+};
 
 mod db_extensions;
 mod history;
@@ -3,7 +3,7 @@ use rusqlite::{Connection, named_params};
 use std::io;
 use std::io::Write;
 
-pub const CURRENT_SCHEMA_VERSION: u16 = 3;
+pub const CURRENT_SCHEMA_VERSION: u16 = 7;
 
 pub fn first_time_setup(connection: &Connection) {
     make_schema_versions_table(connection);
@@ -82,6 +82,72 @@ pub fn migrate(connection: &Connection) {
             .unwrap_or_else(|err| panic!("McFly error: Unable to add selected_commands ({err})"));
     }
 
+    if current_version < 4 {
+        connection
+            .execute_batch(
+                "CREATE TABLE pinned_commands( \
+              id INTEGER PRIMARY KEY AUTOINCREMENT, \
+              cmd TEXT NOT NULL, \
+              cmd_tpl TEXT NOT NULL, \
+              dir TEXT, \
+              when_pinned INTEGER NOT NULL \
+            ); \
+            CREATE UNIQUE INDEX pinned_command_cmd_dirs ON pinned_commands (cmd, IFNULL(dir, ''));",
+            )
+            .unwrap_or_else(|err| panic!("McFly error: Unable to add pinned_commands ({err})"));
+    }
+
+    if current_version < 5 {
+        connection
+            .execute_batch(
+                "CREATE TABLE annotations( \
+              cmd TEXT PRIMARY KEY, \
+              note TEXT NOT NULL, \
+              when_annotated INTEGER NOT NULL \
+            );",
+            )
+            .unwrap_or_else(|err| panic!("McFly error: Unable to add annotations ({err})"));
+    }
+
+    if current_version < 6 {
+        connection
+            .execute_batch(
+                "CREATE TABLE deleted_commands( \
+              id INTEGER PRIMARY KEY, \
+              cmd TEXT NOT NULL, \
+              cmd_tpl TEXT, \
+              session_id TEXT NOT NULL, \
+              when_run INTEGER NOT NULL, \
+              exit_code INTEGER NOT NULL, \
+              selected INTEGER NOT NULL, \
+              dir TEXT, \
+              old_dir TEXT, \
+              deleted_at INTEGER NOT NULL \
+            ); \
+            CREATE INDEX deleted_command_cmds ON deleted_commands (cmd); \
+            CREATE TABLE deleted_selected_commands( \
+              id INTEGER PRIMARY KEY, \
+              cmd TEXT NOT NULL, \
+              session_id TEXT NOT NULL, \
+              dir TEXT NOT NULL \
+            ); \
+            CREATE INDEX deleted_selected_command_cmds ON deleted_selected_commands (cmd);",
+            )
+            .unwrap_or_else(|err| panic!("McFly error: Unable to add deleted_commands ({err})"));
+    }
+
+    if current_version < 7 {
+        connection
+            .execute_batch(
+                "CREATE TABLE retention_runs( \
+              id INTEGER PRIMARY KEY AUTOINCREMENT, \
+              when_run INTEGER NOT NULL, \
+              runs_deleted INTEGER NOT NULL \
+            );",
+            )
+            .unwrap_or_else(|err| panic!("McFly error: Unable to add retention_runs ({err})"));
+    }
+
     if current_version < CURRENT_SCHEMA_VERSION {
         println!("done.");
         write_current_schema_version(connection);
@@ -5,10 +5,23 @@ use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-pub fn clean(settings: &Settings, history: &History, command: &str) {
-    // Clean up the database.
+/// Move `command` to McFly's trash, from where it can be restored. Pinned commands are kept
+/// unless `force` is set, in which case they are unpinned too. Returns whether it was deleted.
+pub fn clean(history: &History, command: &str, force: bool) -> bool {
+    if history.is_pinned(command) {
+        if !force {
+            return false;
+        }
+        history.unpin(command, None);
+    }
+
     history.delete_command(command);
+    true
+}
 
+/// Remove `command` from the shell's history files. This can't be undone, so it is done once
+/// the deletion is final.
+pub fn clean_shell_history(settings: &Settings, command: &str) {
     match settings.history_format {
         HistoryFormat::Bash | HistoryFormat::Zsh { .. } => {
             // Clean up the contents of MCFLY_HISTORY and all other temporary history files in the same
@@ -16,15 +29,9 @@ pub fn clean(settings: &Settings, history: &History, command: &str) {
             clean_temporary_files(&settings.mcfly_history, settings.history_format, command);
 
             // Clean up HISTFILE/MCFLY_HISTFILE.
-            let histfile = PathBuf::from(
-                env::var("HISTFILE")
-                    .or_else(|_| env::var("MCFLY_HISTFILE"))
-                    .unwrap_or_else(|err| {
-                        panic!(
-                            "McFly error: Please ensure that HISTFILE/MCFLY_HISTFILE is set ({err})"
-                        )
-                    }),
-            );
+            let histfile = shell_histfile().unwrap_or_else(|err| {
+                panic!("McFly error: Please ensure that HISTFILE/MCFLY_HISTFILE is set ({err})")
+            });
             shell_history::delete_lines(&histfile, settings.history_format, command);
         }
         // Fish integration does not use a MCFLY_HISTORY file because we can get the last command
@@ -35,6 +42,30 @@ pub fn clean(settings: &Settings, history: &History, command: &str) {
     }
 }
 
+/// Append `command`, restored from the trash, back to the shell's history file that
+/// `clean_shell_history` removed it from. Fish's history is managed by fish, so it isn't touched.
+pub fn restore_shell_history(settings: &Settings, command: &str, when_run: i64) {
+    match settings.history_format {
+        HistoryFormat::Bash | HistoryFormat::Zsh { .. } => match shell_histfile() {
+            Ok(histfile) => {
+                let entry =
+                    shell_history::HistoryCommand::new(command, when_run, settings.history_format);
+                shell_history::append_history_entry(&entry, &histfile, settings.debug);
+            }
+            Err(_) => eprintln!(
+                "McFly: HISTFILE/MCFLY_HISTFILE isn't set, so '{command}' wasn't added back to your shell's history file"
+            ),
+        },
+        HistoryFormat::Fish => {}
+    }
+}
+
+fn shell_histfile() -> Result<PathBuf, env::VarError> {
+    env::var("HISTFILE")
+        .or_else(|_| env::var("MCFLY_HISTFILE"))
+        .map(PathBuf::from)
+}
+
 fn clean_temporary_files(mcfly_history: &Path, history_format: HistoryFormat, command: &str) {
     let path = mcfly_history;
     if let Some(directory) = path.parent() {
@@ -0,0 +1,197 @@
+use crate::path_update_helpers::normalize_path;
+use config::Value;
+use regex::Regex;
+use std::fmt;
+use std::path::Path;
+
+/// Commands that are never recorded, unless `ignore_defaults` is turned off.
+const DEFAULT_IGNORED_COMMANDS: [&str; 7] = [
+    "pwd",
+    "ls",
+    "cd",
+    "cd ..",
+    "clear",
+    "history",
+    "mcfly search",
+];
+
+#[derive(Debug, Clone)]
+pub enum IgnorePattern {
+    /// Matches the whole command exactly.
+    Literal(String),
+    /// Matches the whole command, with `*` matching anything and `?` matching any one character.
+    Glob(String, Regex),
+    /// Matches any command containing a match for the regex.
+    Regex(Regex),
+}
+
+/// A rule for commands that shouldn't be recorded.
+#[derive(Debug, Clone)]
+pub struct IgnoreRule {
+    pub pattern: IgnorePattern,
+    /// Only ignore commands run in this directory or below it.
+    pub dir: Option<String>,
+}
+
+impl IgnoreRule {
+    /// Parse a rule written as a string: `glob:<glob>`, `regex:<regex>`, or a literal command.
+    pub fn parse(value: &str) -> Result<IgnoreRule, String> {
+        let pattern = if let Some(glob) = value.strip_prefix("glob:") {
+            IgnorePattern::glob(glob)?
+        } else if let Some(regex) = value.strip_prefix("regex:") {
+            IgnorePattern::regex(regex)?
+        } else {
+            IgnorePattern::Literal(value.to_string())
+        };
+        Ok(IgnoreRule { pattern, dir: None })
+    }
+
+    /// Parse a rule from `config.toml`, either as a string or as a table with one of `literal`,
+    /// `glob` or `regex`, and optionally a `dir` to limit it to.
+    pub fn from_config(value: Value) -> Result<IgnoreRule, String> {
+        if let Ok(value) = value.clone().into_string() {
+            return IgnoreRule::parse(&value);
+        }
+
+        let table = value
+            .into_table()
+            .map_err(|_| String::from("expected a string or a table"))?;
+        let string = |key: &str| table.get(key).and_then(|v| v.clone().into_string().ok());
+        let pattern = match (string("literal"), string("glob"), string("regex")) {
+            (Some(literal), None, None) => IgnorePattern::Literal(literal),
+            (None, Some(glob), None) => IgnorePattern::glob(&glob)?,
+            (None, None, Some(regex)) => IgnorePattern::regex(&regex)?,
+            _ => return Err(String::from("expected one of literal, glob or regex")),
+        };
+        let dir = string("dir").map(|dir| normalize_path(&dir));
+        Ok(IgnoreRule { pattern, dir })
+    }
+
+    /// Whether this rule ignores `command` run in `dir`. Rules limited to a directory never
+    /// match commands whose directory isn't known.
+    #[must_use]
+    pub fn matches(&self, command: &str, dir: Option<&str>) -> bool {
+        if let Some(rule_dir) = &self.dir
+            && !dir.is_some_and(|dir| Path::new(dir).starts_with(rule_dir))
+        {
+            return false;
+        }
+
+        match &self.pattern {
+            IgnorePattern::Literal(literal) => command == literal,
+            IgnorePattern::Glob(_, regex) | IgnorePattern::Regex(regex) => regex.is_match(command),
+        }
+    }
+}
+
+impl fmt::Display for IgnoreRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.pattern {
+            IgnorePattern::Literal(literal) => write!(f, "'{literal}'")?,
+            IgnorePattern::Glob(glob, _) => write!(f, "'glob:{glob}'")?,
+            IgnorePattern::Regex(regex) => write!(f, "'regex:{regex}'")?,
+        }
+        if let Some(dir) = &self.dir {
+            write!(f, " in {dir}")?;
+        }
+        Ok(())
+    }
+}
+
+impl IgnorePattern {
+    fn glob(glob: &str) -> Result<IgnorePattern, String> {
+        let mut regex = String::from("^");
+        for c in glob.chars() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+
+        // (?s) so that wildcards match the newlines in multi-line commands.
+        Regex::new(&format!("(?s){regex}"))
+            .map(|regex| IgnorePattern::Glob(glob.to_string(), regex))
+            .map_err(|err| format!("invalid glob '{glob}' ({err})"))
+    }
+
+    fn regex(regex: &str) -> Result<IgnorePattern, String> {
+        Regex::new(regex)
+            .map(IgnorePattern::Regex)
+            .map_err(|err| format!("invalid regex '{regex}' ({err})"))
+    }
+}
+
+/// The built-in rules.
+#[must_use]
+pub fn default_rules() -> Vec<IgnoreRule> {
+    DEFAULT_IGNORED_COMMANDS
+        .iter()
+        .map(|command| IgnoreRule {
+            pattern: IgnorePattern::Literal((*command).to_string()),
+            dir: None,
+        })
+        .collect()
+}
+
+/// The first of `rules` that ignores `command` run in `dir`.
+#[must_use]
+pub fn matching_rule<'r>(
+    rules: &'r [IgnoreRule],
+    command: &str,
+    dir: Option<&str>,
+) -> Option<&'r IgnoreRule> {
+    rules.iter().find(|rule| rule.matches(command, dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IgnoreRule, matching_rule};
+
+    #[test]
+    fn match_literals_globs_and_regexes() {
+        let literal = IgnoreRule::parse("cd ..").unwrap();
+        assert!(literal.matches("cd ..", None));
+        assert!(!literal.matches("cd ../..", None));
+
+        let glob = IgnoreRule::parse("glob:git st*").unwrap();
+        assert!(glob.matches("git status", None));
+        assert!(glob.matches("git stash pop", None));
+        assert!(!glob.matches("sudo git status", None));
+        assert!(
+            IgnoreRule::parse("glob:ls -?")
+                .unwrap()
+                .matches("ls -l", None)
+        );
+        assert!(IgnoreRule::parse("glob:a.b").unwrap().matches("a.b", None));
+        assert!(!IgnoreRule::parse("glob:a.b").unwrap().matches("axb", None));
+
+        let regex = IgnoreRule::parse("regex:--password(=| )").unwrap();
+        assert!(regex.matches("mysql --password hunter2", None));
+        assert!(!regex.matches("mysql --user me", None));
+
+        let perl_classes = IgnoreRule::parse(r"regex:(?i)\bsecret\s+\d+\w*").unwrap();
+        assert!(perl_classes.matches("vault put SECRET 42abc", None));
+        assert!(!perl_classes.matches("vault put nosecret 42", None));
+
+        assert!(IgnoreRule::parse("regex:(").is_err());
+    }
+
+    #[test]
+    fn match_rules_limited_to_a_directory() {
+        let mut rule = IgnoreRule::parse("make deploy").unwrap();
+        rule.dir = Some(String::from("/work/app"));
+        assert!(rule.matches("make deploy", Some("/work/app")));
+        assert!(rule.matches("make deploy", Some("/work/app/sub")));
+        assert!(!rule.matches("make deploy", Some("/work/application")));
+        assert!(!rule.matches("make deploy", None));
+
+        let rules = vec![rule, IgnoreRule::parse("ls").unwrap()];
+        assert_eq!(
+            matching_rule(&rules, "ls", Some("/tmp")).map(ToString::to_string),
+            Some(String::from("'ls'"))
+        );
+        assert!(matching_rule(&rules, "make deploy", Some("/tmp")).is_none());
+    }
+}
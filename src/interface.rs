@@ -1,35 +1,73 @@
+use crate::clipboard;
 use crate::command_input::{CommandInput, Move};
 use crate::history::History;
 
 use crate::fixed_length_grapheme_string::FixedLengthGraphemeString;
-use crate::history::Command;
+use crate::history::{Command, PinnedCommand};
 use crate::history_cleaner;
-use crate::settings::{InterfaceView, KeyScheme, ResultFilter};
-use crate::settings::{ResultSort, Settings};
-use chrono::{Duration, TimeZone, Utc};
+use crate::key_bindings::Action;
+use crate::settings::{ColumnKind, Palette, ResultColumn, ResultSort, Settings};
+use crate::settings::{FailureFilter, InterfaceView, KeyScheme, PreviewPosition, ResultFilter};
+use crate::shell_syntax::{self, TokenKind};
+use chrono::{Duration, Local, TimeZone, Utc};
 use crossterm::event::KeyCode::Char;
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, read};
-use crossterm::style::{Color, Print, SetBackgroundColor, SetForegroundColor};
+use crossterm::event::{
+    DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+    MouseButton, MouseEvent, MouseEventKind, read,
+};
+use crossterm::style::{
+    Attribute, Color, Colored, Print, SetAttribute, SetBackgroundColor, SetForegroundColor,
+};
 use crossterm::terminal::{self, LeaveAlternateScreen};
 use crossterm::terminal::{Clear, ClearType, EnterAlternateScreen};
 use crossterm::{cursor, execute, queue};
 use humantime::format_duration;
-use std::io::{Write, stdout};
+use rand::Rng;
+use std::io::{self, Write, stdout};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
 use std::string::String;
+use std::time::{Duration as StdDuration, Instant};
+use std::{env, fs, process};
+use unicode_segmentation::UnicodeSegmentation;
 
 pub struct Interface<'a> {
     history: &'a History,
     settings: &'a Settings,
     input: CommandInput,
+    /// The note being edited for the selected command, in `MenuMode::EditNote`.
+    note_input: CommandInput,
     selection: usize,
     matches: Vec<Command>,
     debug: bool,
     run: bool,
     delete_requests: Vec<String>,
+    /// Commands deleted during this search, most recent last, with the pins they had. Deleting
+    /// them from the shell's history waits until the interface exits, so that they can be undone.
+    deleted: Vec<(String, Vec<PinnedCommand>)>,
+    accept_dir: Option<String>,
     menu_mode: MenuMode,
     in_vim_insert_mode: bool,
     result_sort: ResultSort,
     result_filter: ResultFilter,
+    failure_filter: FailureFilter,
+    group_results: bool,
+    expanded_template: Option<String>,
+    show_preview: bool,
+    marked: Vec<String>,
+    /// The terminal row the interface is drawn from, which is only non-zero when drawing inline.
+    origin: u16,
+    /// The number of rows used when drawing inline instead of on the alternate screen.
+    inline_rows: Option<u16>,
+    /// The cursor column the interface was opened from, restored when drawing inline.
+    start_column: u16,
+    /// Whether ctrl-x was just pressed, as the start of ctrl-x ctrl-e.
+    pending_ctrl_x: bool,
+    /// A message shown in place of the menubar until the next event.
+    flash: Option<String>,
+    /// When and where the last mouse click was, to detect double clicks.
+    last_click: Option<(Instant, usize)>,
 }
 
 pub struct SelectionResult {
@@ -39,6 +77,9 @@ pub struct SelectionResult {
     pub selection: Option<String>,
     /// Commands the user has requested be deleted from shell history.
     pub delete_requests: Vec<String>,
+    /// The directory to change to before running the selection, if the user asked to run it
+    /// where it was originally run.
+    pub dir: Option<String>,
 }
 
 pub enum MoveSelection {
@@ -50,59 +91,164 @@ pub enum MoveSelection {
 pub enum MenuMode {
     Normal,
     ConfirmDelete,
+    EditNote,
 }
 
 impl MenuMode {
     fn text(&self, interface: &Interface) -> String {
-        let mut menu_text = String::from("McFly");
-        match *self {
-            MenuMode::Normal => match interface.settings.key_scheme {
-                KeyScheme::Emacs => menu_text.push_str(" | ESC - Exit | "),
-                KeyScheme::Vim => {
-                    if interface.in_vim_insert_mode {
-                        menu_text.push_str(" (Ins) | ESC - Cmd | ");
+        self.items(interface)
+            .into_iter()
+            .map(|(text, _action)| text)
+            .collect::<Vec<String>>()
+            .join(MENU_SEPARATOR)
+    }
+
+    /// The menubar items, each with the action performed when it is clicked.
+    fn items(&self, interface: &Interface) -> Vec<(String, Option<Action>)> {
+        if *self == MenuMode::ConfirmDelete {
+            let pinned = interface
+                .matches
+                .get(interface.selection)
+                .is_some_and(|command| interface.history.is_pinned(&command.cmd));
+            let text = if pinned {
+                "Unpin and delete selected command from the history? (Y/N)"
+            } else {
+                "Delete selected command from the history? (Y/N)"
+            };
+            return vec![(String::from(text), None)];
+        }
+
+        if *self == MenuMode::EditNote {
+            return vec![(
+                format!("{NOTE_LABEL}{}", interface.note_input.command),
+                None,
+            )];
+        }
+
+        let mut title = String::from("McFly");
+        if !interface.marked.is_empty() {
+            title.push_str(&format!(" ({} Marked)", interface.marked.len()));
+        }
+
+        let mut items: Vec<(String, Option<Action>)> = Vec::new();
+        match interface.settings.key_scheme {
+            KeyScheme::Emacs => {
+                items.push((title, None));
+                items.push((String::from("ESC - Exit"), Some(Action::Exit)));
+            }
+            KeyScheme::Vim => {
+                if interface.in_vim_insert_mode {
+                    items.push((format!("{title} (Ins)"), None));
+                    items.push((String::from("ESC - Cmd"), None));
+                } else {
+                    items.push((format!("{title} (Cmd)"), None));
+                    items.push((String::from("ESC - Exit"), Some(Action::Exit)));
+                }
+            }
+        }
+
+        let allow_plain_chars = matches!(interface.settings.key_scheme, KeyScheme::Vim)
+            && !interface.in_vim_insert_mode;
+        let key = |action| {
+            interface
+                .settings
+                .key_bindings
+                .label(action, allow_plain_chars)
+        };
+
+        match (key(Action::Accept), key(Action::Edit)) {
+            (Some(accept), Some(edit)) if interface.settings.disable_run_command => {
+                items.push((format!("{accept}, {edit} - Edit"), Some(Action::Edit)));
+            }
+            (accept, edit) => {
+                if let Some(accept) = accept {
+                    if interface.settings.disable_run_command {
+                        items.push((format!("{accept} - Edit"), Some(Action::Edit)));
                     } else {
-                        menu_text.push_str(" (Cmd) | ESC - Exit | ");
+                        items.push((format!("{accept} - Run"), Some(Action::Accept)));
                     }
                 }
-            },
-            MenuMode::ConfirmDelete => {
-                return String::from("Delete selected command from the history? (Y/N)");
+                if let Some(edit) = edit {
+                    items.push((format!("{edit} - Edit"), Some(Action::Edit)));
+                }
             }
         }
 
-        if interface.settings.disable_run_command {
-            menu_text.push_str("⏎, TAB - Edit | ");
-        } else {
-            menu_text.push_str("⏎ - Run | TAB - Edit | ");
+        if let Some(key) = key(Action::ToggleSort) {
+            let sort = match interface.result_sort {
+                ResultSort::Rank => "Rank Sort",
+                ResultSort::LastRun => "Time Sort",
+                ResultSort::Frequency => "Frequency Sort",
+                ResultSort::Frecency => "Frecency Sort",
+                ResultSort::RecentHere => "Recent Here Sort",
+            };
+            items.push((format!("{key} - {sort}"), Some(Action::ToggleSort)));
+        }
+
+        if let Some(key) = key(Action::Delete) {
+            items.push((format!("{key} - Delete"), Some(Action::Delete)));
+        }
+
+        if let Some(key) = key(Action::ToggleFilter) {
+            let filter = match interface.result_filter {
+                ResultFilter::Global => "All Directories",
+                ResultFilter::CurrentDirectory => "This Directory",
+                ResultFilter::CurrentSession => "This Session",
+                ResultFilter::Successful => "Successful",
+            };
+            items.push((format!("{key} - {filter}"), Some(Action::ToggleFilter)));
         }
 
-        match interface.result_sort {
-            ResultSort::Rank => menu_text.push_str("F1 - Rank Sort | "),
-            ResultSort::LastRun => menu_text.push_str("F1 - Time Sort | "),
+        if let Some(key) = key(Action::ToggleGrouping) {
+            let grouping = if interface.group_results {
+                "Grouped"
+            } else {
+                "Ungrouped"
+            };
+            items.push((format!("{key} - {grouping}"), Some(Action::ToggleGrouping)));
         }
 
-        menu_text.push_str("F2 - Delete | ");
+        if let Some(key) = key(Action::ToggleFailures) {
+            let failures = match interface.failure_filter {
+                FailureFilter::Show => "Failures Shown",
+                FailureFilter::Hide => "Failures Hidden",
+                FailureFilter::Only => "Failures Only",
+            };
+            items.push((format!("{key} - {failures}"), Some(Action::ToggleFailures)));
+        }
+
+        if let Some(key) = key(Action::TogglePreview) {
+            items.push((format!("{key} - Preview"), Some(Action::TogglePreview)));
+        }
+
+        if let Some(key) = key(Action::TogglePin) {
+            items.push((format!("{key} - Pin"), Some(Action::TogglePin)));
+        }
 
-        match interface.result_filter {
-            ResultFilter::Global => menu_text.push_str("F3 - All Directories"),
-            ResultFilter::CurrentDirectory => menu_text.push_str("F3 - This Directory"),
+        if let Some(key) = key(Action::Annotate) {
+            items.push((format!("{key} - Note"), Some(Action::Annotate)));
         }
 
-        menu_text
+        items
     }
 
     fn bg(&self, normal: Color) -> Color {
         match *self {
             MenuMode::Normal => normal,
             MenuMode::ConfirmDelete => Color::Red,
+            MenuMode::EditNote => normal,
         }
     }
 }
 
+const MENU_SEPARATOR: &str = " | ";
+const NEWLINE_MARKER: &str = "⏎";
+const NOTE_LABEL: &str = "Note: ";
 const PROMPT_LINE_INDEX: u16 = 3;
 const INFO_LINE_INDEX: u16 = 1;
 const RESULTS_TOP_INDEX: u16 = 5;
+const PREVIEW_RUNS: i16 = 10;
+const DOUBLE_CLICK_INTERVAL: StdDuration = StdDuration::from_millis(400);
 
 impl<'a> Interface<'a> {
     pub fn new(settings: &'a Settings, history: &'a History) -> Interface<'a> {
@@ -110,15 +256,29 @@ impl<'a> Interface<'a> {
             history,
             settings,
             input: CommandInput::from(settings.command.clone()),
+            note_input: CommandInput::from(""),
             selection: 0,
             matches: Vec::new(),
             debug: settings.debug,
             run: false,
             delete_requests: Vec::new(),
+            deleted: Vec::new(),
+            accept_dir: None,
             menu_mode: MenuMode::Normal,
             in_vim_insert_mode: true,
             result_sort: settings.result_sort.clone(),
             result_filter: settings.result_filter.clone(),
+            failure_filter: settings.failure_filter.clone(),
+            group_results: settings.group_results,
+            expanded_template: None,
+            show_preview: settings.preview,
+            marked: Vec::new(),
+            origin: 0,
+            inline_rows: None,
+            start_column: 0,
+            pending_ctrl_x: false,
+            flash: None,
+            last_click: None,
         }
     }
 
@@ -126,25 +286,44 @@ impl<'a> Interface<'a> {
         self.build_cache_table();
         self.select();
 
+        // Deletions can no longer be undone from here, so the shell's history files are cleaned
+        // now. `mcfly trash restore` appends the command back to them.
+        for (deleted, _pins) in self.deleted.split_off(0) {
+            history_cleaner::clean_shell_history(self.settings, &deleted);
+            self.delete_requests.push(deleted);
+        }
+
         let command = self.input.command.clone();
 
         if command.chars().any(|c| !c.is_whitespace()) {
-            self.history.record_selected_from_ui(
-                &command,
-                &self.settings.session_id,
-                &self.settings.dir,
-            );
+            if self.marked.is_empty() {
+                self.history.record_selected_from_ui(
+                    &command,
+                    &self.settings.session_id,
+                    self.accept_dir.as_ref().unwrap_or(&self.settings.dir),
+                );
+            } else {
+                for marked in &self.marked {
+                    self.history.record_selected_from_ui(
+                        marked,
+                        &self.settings.session_id,
+                        &self.settings.dir,
+                    );
+                }
+            }
             SelectionResult {
                 run: self.run,
                 selection: Some(command),
                 // Remove delete_requests from the Interface, in case it's used to display() again.
                 delete_requests: self.delete_requests.split_off(0),
+                dir: self.accept_dir.take(),
             }
         } else {
             SelectionResult {
                 run: self.run,
                 selection: None,
                 delete_requests: self.delete_requests.split_off(0),
+                dir: None,
             }
         }
     }
@@ -153,6 +332,7 @@ impl<'a> Interface<'a> {
         self.history.build_cache_table(
             &self.settings.dir.clone(),
             &self.result_filter,
+            &self.failure_filter,
             &Some(self.settings.session_id.clone()),
             None,
             None,
@@ -161,24 +341,54 @@ impl<'a> Interface<'a> {
         );
     }
 
+    fn palette(&self) -> &Palette {
+        if self.settings.no_color {
+            &Palette::NO_COLOR
+        } else {
+            self.settings.colors.palette(self.settings.lightmode)
+        }
+    }
+
+    /// `color`, unless colors are disabled with `NO_COLOR`.
+    fn color(&self, color: Color) -> Color {
+        if self.settings.no_color {
+            Color::Reset
+        } else {
+            color
+        }
+    }
+
     fn menubar<W: Write>(&self, screen: &mut W) {
-        if !self.settings.disable_menu {
-            let (width, _height): (u16, u16) = terminal::size().unwrap();
+        // The note editor lives in the menubar, so it is shown even when the menu is disabled.
+        if !self.settings.disable_menu || self.menu_mode == MenuMode::EditNote {
+            let (width, _height): (u16, u16) = self.size();
 
             queue!(
                 screen,
                 cursor::Hide,
-                cursor::MoveTo(0, self.info_line_index()),
+                self.move_to(0, self.info_line_index()),
                 Clear(ClearType::CurrentLine),
-                SetBackgroundColor(self.menu_mode.bg(self.settings.colors.menubar_bg)),
-                SetForegroundColor(self.settings.colors.menubar_fg),
-                cursor::MoveTo(1, self.info_line_index()),
+                SetBackgroundColor(self.color(self.menu_mode.bg(self.settings.colors.menubar_bg))),
+                SetForegroundColor(self.color(self.settings.colors.menubar_fg)),
+                SetAttribute(if self.settings.no_color {
+                    Attribute::Reverse
+                } else {
+                    Attribute::NoReverse
+                }),
+                self.move_to(1, self.info_line_index()),
                 Print(format!(
                     "{text:width$}",
-                    text = self.menu_mode.text(self),
+                    text = FixedLengthGraphemeString::new(
+                        self.flash
+                            .clone()
+                            .unwrap_or_else(|| self.menu_mode.text(self)),
+                        width - 1
+                    )
+                    .string,
                     width = width as usize - 1
                 )),
-                SetBackgroundColor(Color::Reset)
+                SetBackgroundColor(Color::Reset),
+                SetAttribute(Attribute::NoReverse)
             )
             .unwrap();
         }
@@ -186,21 +396,82 @@ impl<'a> Interface<'a> {
 
     fn prompt<W: Write>(&self, screen: &mut W) {
         let prompt_line_index = self.prompt_line_index();
-        let fg = if self.settings.lightmode {
-            self.settings.colors.lightmode_colors.prompt
-        } else {
-            self.settings.colors.darkmode_colors.prompt
-        };
         queue!(
             screen,
-            cursor::MoveTo(1, prompt_line_index),
-            SetForegroundColor(fg),
+            self.move_to(1, prompt_line_index),
+            SetForegroundColor(self.palette().prompt),
             Clear(ClearType::CurrentLine),
-            Print(format!("{} {}", self.settings.prompt, self.input)),
-            cursor::MoveTo(self.input.cursor as u16 + 3, prompt_line_index),
+            Print(format!(
+                "{} {}",
+                self.settings.prompt,
+                self.input.command.replace('\n', NEWLINE_MARKER)
+            )),
+            self.move_to(self.input.cursor as u16 + 3, prompt_line_index),
             cursor::Show
         )
         .unwrap();
+
+        if self.menu_mode == MenuMode::EditNote {
+            let column = 1 + NOTE_LABEL.len() as u16 + self.note_input.cursor as u16;
+            queue!(screen, self.move_to(column, self.info_line_index())).unwrap();
+        }
+    }
+
+    fn syntax_color(&self, kind: TokenKind, base_color: Color) -> Color {
+        let palette = self.palette();
+        match kind {
+            TokenKind::Plain => base_color,
+            TokenKind::Command => palette.syntax_command,
+            TokenKind::Flag => palette.syntax_flag,
+            TokenKind::String => palette.syntax_string,
+            TokenKind::Variable => palette.syntax_variable,
+            TokenKind::Operator => palette.syntax_operator,
+        }
+    }
+
+    /// The text of a result column for `command`, and the color to show it in by default.
+    fn column_text(&self, column: &ResultColumn, command: &Command) -> (String, Color) {
+        let timing_color = self.palette().timing;
+
+        match column.kind {
+            ColumnKind::RelativeTime => (
+                command.last_run.map(relative_time).unwrap_or_default(),
+                timing_color,
+            ),
+            ColumnKind::Time => (
+                command
+                    .last_run
+                    .and_then(|last_run| Local.timestamp_opt(last_run, 0).single())
+                    .map(|last_run| last_run.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default(),
+                timing_color,
+            ),
+            ColumnKind::Status => match command.exit_code {
+                Some(0) => (String::from("✓"), Color::Green),
+                Some(_) => (String::from("✗"), Color::Red),
+                None => (String::new(), timing_color),
+            },
+            ColumnKind::Runs if command.occurrences > 0 => {
+                (format!("{}×", command.occurrences), timing_color)
+            }
+            ColumnKind::Runs => (String::new(), timing_color),
+            ColumnKind::Dir => (
+                command
+                    .dir
+                    .as_deref()
+                    .map(|dir| {
+                        abbreviate_dir(
+                            dir,
+                            env::var("HOME").ok().as_deref(),
+                            column.width.saturating_sub(1) as usize,
+                        )
+                    })
+                    .unwrap_or_default(),
+                timing_color,
+            ),
+            ColumnKind::Session if command.in_session => (String::from("•"), timing_color),
+            ColumnKind::Session => (String::new(), timing_color),
+        }
     }
 
     fn debug_cursor<W: Write>(&self, screen: &mut W) {
@@ -208,131 +479,113 @@ impl<'a> Interface<'a> {
         queue!(
             screen,
             cursor::Hide,
-            cursor::MoveTo(0, result_top_index + self.settings.results + 1)
+            self.move_to(0, result_top_index + self.settings.results + 1)
         )
         .unwrap();
     }
 
     fn results<W: Write>(&mut self, screen: &mut W) {
         let result_top_index = self.result_top_index();
-        queue!(screen, cursor::Hide, cursor::MoveTo(1, result_top_index)).unwrap();
+        queue!(screen, cursor::Hide, self.move_to(1, result_top_index)).unwrap();
 
-        let (width, height): (u16, u16) = terminal::size().unwrap();
-        let result_height = (height - RESULTS_TOP_INDEX) as usize
-            + if self.is_screen_view_bottom() { 1 } else { 0 };
+        let (width, _height): (u16, u16) = self.size();
+        let width = self.results_width(width);
+        let result_height = self.result_height();
+        if result_height == 0 {
+            return;
+        }
+        let columns_width: u16 = self.settings.columns.iter().map(|c| c.width).sum();
 
         if !self.matches.is_empty() && self.selection > self.matches.len() - 1 {
             self.selection = self.matches.len() - 1;
         }
 
         let mut index = 0;
-        let in_page = self.selection < result_height;
 
-        let view_range = if in_page {
-            let len = result_height.min(self.matches.len());
-            &self.matches[..len]
-        } else {
-            let offset = self.selection - result_height + 1;
-            let len = (offset + result_height).min(self.matches.len());
-            &self.matches[offset..len]
-        };
+        let offset = self.view_offset(result_height);
+        let len = (offset + result_height).min(self.matches.len());
+        let view_range = &self.matches[offset..len];
 
         for command in view_range {
-            let mut fg = if self.settings.lightmode {
-                self.settings.colors.lightmode_colors.results_fg
+            let palette = self.palette();
+            let selected = index == self.selection.min(result_height - 1);
+            let (fg, bg, highlight) = if selected {
+                (
+                    palette.results_selection_fg,
+                    palette.results_selection_bg,
+                    palette.results_selection_hl,
+                )
             } else {
-                self.settings.colors.darkmode_colors.results_fg
+                (palette.results_fg, Color::Reset, palette.results_hl)
             };
-
-            let mut highlight = if self.settings.lightmode {
-                self.settings.colors.lightmode_colors.results_hl
+            // Without colors, the selection is shown in reverse video instead.
+            let attribute = if selected && self.settings.no_color {
+                Attribute::Reverse
             } else {
-                self.settings.colors.darkmode_colors.results_hl
+                Attribute::NoReverse
             };
 
-            let mut bg = Color::Reset;
-
-            if index == self.selection.min(result_height - 1) {
-                if self.settings.lightmode {
-                    fg = self.settings.colors.lightmode_colors.results_selection_fg;
-                    bg = self.settings.colors.lightmode_colors.results_selection_bg;
-                    highlight = self.settings.colors.lightmode_colors.results_selection_hl;
-                } else {
-                    fg = self.settings.colors.darkmode_colors.results_selection_fg;
-                    bg = self.settings.colors.darkmode_colors.results_selection_bg;
-                    highlight = self.settings.colors.darkmode_colors.results_selection_hl;
-                }
-            }
+            // The selected row keeps the selection colors, so that it stays legible.
+            let syntax_colors: Vec<Color> = if self.settings.syntax_highlight && !selected {
+                shell_syntax::tokenize(&command.cmd)
+                    .into_iter()
+                    .map(|kind| self.syntax_color(kind, fg))
+                    .collect()
+            } else {
+                Vec::new()
+            };
 
             let command_line_index = self.command_line_index(index as i16);
             queue!(
                 screen,
-                cursor::MoveTo(1, (command_line_index + result_top_index as i16) as u16),
+                self.move_to(1, (command_line_index + result_top_index as i16) as u16),
                 Clear(ClearType::CurrentLine),
                 SetBackgroundColor(bg),
                 SetForegroundColor(fg),
+                SetAttribute(attribute),
                 Print(Interface::truncate_for_display(
-                    command, width, highlight, fg, self.debug
+                    command,
+                    &format!(
+                        "{}{}{}",
+                        self.mark_prefix(command),
+                        self.pin_prefix(command),
+                        self.group_prefix(command)
+                    ),
+                    width,
+                    columns_width,
+                    highlight,
+                    fg,
+                    &syntax_colors,
+                    self.settings.no_color,
+                    self.debug
                 ))
             )
             .unwrap();
 
-            if let Some(last_run) = command.last_run {
+            let row = (command_line_index + result_top_index as i16) as u16;
+            let mut column_x = width.saturating_sub(columns_width);
+            for column in &self.settings.columns {
+                let (text, default_color) = self.column_text(column, command);
+                let column_width = column.width as usize;
                 queue!(
                     screen,
-                    cursor::MoveTo(
-                        width - 9,
-                        (command_line_index + result_top_index as i16) as u16
-                    )
-                )
-                .unwrap();
-
-                let duration = &format_duration(
-                    Duration::minutes(
-                        Utc::now()
-                            .signed_duration_since(Utc.timestamp_opt(last_run, 0).unwrap())
-                            .num_minutes(),
-                    )
-                    .to_std()
-                    .unwrap(),
-                )
-                .to_string()
-                .split(' ')
-                .take(2)
-                .map(|s| {
-                    s.replace("years", "y")
-                        .replace("year", "y")
-                        .replace("months", "mo")
-                        .replace("month", "mo")
-                        .replace("days", "d")
-                        .replace("day", "d")
-                        .replace("hours", "h")
-                        .replace("hour", "h")
-                        .replace("minutes", "m")
-                        .replace("minute", "m")
-                        .replace("0s", "< 1m")
-                })
-                .collect::<Vec<String>>()
-                .join(" ");
-
-                let timing_color = if self.settings.lightmode {
-                    self.settings.colors.lightmode_colors.timing
-                } else {
-                    self.settings.colors.darkmode_colors.timing
-                };
-                queue!(
-                    screen,
-                    cursor::MoveTo(
-                        width - 9,
-                        (command_line_index + self.result_top_index() as i16) as u16
-                    ),
-                    SetForegroundColor(timing_color),
-                    Print(format!("{duration:>9}")),
-                    SetForegroundColor(Color::Reset),
-                    SetBackgroundColor(Color::Reset)
+                    self.move_to(column_x, row),
+                    SetForegroundColor(self.color(column.color.unwrap_or(default_color))),
+                    Print(format!(
+                        "{:>column_width$}",
+                        FixedLengthGraphemeString::new(text, column.width.saturating_sub(1)).string
+                    ))
                 )
                 .unwrap();
+                column_x += column.width;
             }
+            queue!(
+                screen,
+                SetForegroundColor(Color::Reset),
+                SetBackgroundColor(Color::Reset),
+                SetAttribute(Attribute::NoReverse)
+            )
+            .unwrap();
             index += 1;
         }
 
@@ -342,18 +595,140 @@ impl<'a> Interface<'a> {
             let command_line_index = self.command_line_index(i as i16);
             queue!(
                 screen,
-                cursor::MoveTo(1, (command_line_index + result_top_index as i16) as u16),
+                self.move_to(1, (command_line_index + result_top_index as i16) as u16),
                 Clear(ClearType::CurrentLine)
             )
             .unwrap();
         }
     }
 
+    fn preview<W: Write>(&self, screen: &mut W) {
+        if !self.show_preview {
+            return;
+        }
+
+        let (x, y, width, height) = self.preview_area();
+        let (fg, heading) = (self.palette().results_fg, self.palette().timing);
+
+        // Each line is paired with whether it is a heading.
+        let mut lines: Vec<(String, bool)> = Vec::new();
+        if let Some(command) = self.matches.get(self.selection) {
+            let details = self.history.command_details(&command.cmd, PREVIEW_RUNS);
+
+            lines.push((String::from("Command"), true));
+            for line in details.cmd.lines() {
+                for wrapped in Interface::wrap(line, width.saturating_sub(2)) {
+                    lines.push((format!("  {wrapped}"), false));
+                }
+            }
+
+            if let Some(note) = &command.note {
+                lines.push((String::new(), false));
+                lines.push((String::from("Note"), true));
+                for line in note.lines() {
+                    for wrapped in Interface::wrap(line, width.saturating_sub(2)) {
+                        lines.push((format!("  {wrapped}"), false));
+                    }
+                }
+            }
+
+            lines.push((String::new(), false));
+            let times = if details.count == 1 { "time" } else { "times" };
+            lines.push((format!("Run {} {times}", details.count), true));
+            if let Some(last_run) = details.runs.first() {
+                lines.push((format!("  Last session: {}", last_run.session_id), false));
+            }
+
+            lines.push((String::new(), false));
+            lines.push((String::from("Directories"), true));
+            for (dir, count) in &details.dirs {
+                let dir = dir.as_deref().unwrap_or("(unknown)");
+                lines.push((format!("  {count:>5}  {dir}"), false));
+            }
+
+            lines.push((String::new(), false));
+            lines.push((String::from("Recent runs"), true));
+            for run in &details.runs {
+                let when = run
+                    .when_run
+                    .and_then(|when_run| Local.timestamp_opt(when_run, 0).single())
+                    .map(|when_run| when_run.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default();
+                let status = match run.exit_code {
+                    Some(0) => String::from("✓"),
+                    Some(exit_code) => format!("✗ {exit_code}"),
+                    None => String::from("?"),
+                };
+                lines.push((format!("  {when}  {status:<5} {}", run.session_id), false));
+            }
+        }
+
+        // A bottom pane is separated from the results by a horizontal rule on the side facing them.
+        let (border, text_width) = match self.settings.preview_position {
+            PreviewPosition::Side => ("│ ", width.saturating_sub(2)),
+            PreviewPosition::Bottom => ("", width),
+        };
+        let (first_row, separator_row) = match self.settings.preview_position {
+            PreviewPosition::Side => (0, None),
+            PreviewPosition::Bottom if self.is_screen_view_bottom() => {
+                (0, Some(height.saturating_sub(1)))
+            }
+            PreviewPosition::Bottom => (1, Some(0)),
+        };
+
+        if let Some(separator_row) = separator_row {
+            queue!(
+                screen,
+                self.move_to(x, y + separator_row),
+                SetForegroundColor(heading),
+                Print("─".repeat(width as usize))
+            )
+            .unwrap();
+        }
+
+        for row in (0..height).filter(|row| Some(*row) != separator_row) {
+            let (text, is_heading) = lines
+                .get((row - first_row) as usize)
+                .map(|(text, is_heading)| (text.as_str(), *is_heading))
+                .unwrap_or(("", false));
+
+            let line = FixedLengthGraphemeString::new(text, text_width);
+            let padding = " ".repeat((text_width - line.grapheme_length) as usize);
+            queue!(
+                screen,
+                self.move_to(x, y + row),
+                SetForegroundColor(heading),
+                Print(border),
+                SetForegroundColor(if is_heading { heading } else { fg }),
+                Print(line.string),
+                Print(padding),
+                SetForegroundColor(Color::Reset)
+            )
+            .unwrap();
+        }
+    }
+
+    /// Split `text` into lines of at most `width` graphemes.
+    fn wrap(text: &str, width: u16) -> Vec<String> {
+        let graphemes = text.graphemes(true).collect::<Vec<&str>>();
+        if graphemes.is_empty() || width == 0 {
+            return vec![String::new()];
+        }
+        graphemes
+            .chunks(width as usize)
+            .map(|chunk| chunk.concat())
+            .collect()
+    }
+
+    fn toggle_preview(&mut self) {
+        self.show_preview = !self.show_preview;
+    }
+
     #[allow(unused)]
     fn debug<W: Write, S: Into<String>>(&self, screen: &mut W, s: S) {
         queue!(
             screen,
-            cursor::MoveTo(0, 0),
+            self.move_to(0, 0),
             Clear(ClearType::CurrentLine),
             Print(s.into())
         )
@@ -388,9 +763,162 @@ impl<'a> Interface<'a> {
         }
     }
 
+    // At the end of the input, moving right expands or collapses the selected template group.
+    fn move_right(&mut self) {
+        if self.input.cursor == self.input.len {
+            self.toggle_expansion();
+        } else {
+            self.input.move_cursor(Move::Forward);
+        }
+    }
+
+    /// The marked commands joined together, or else the selected command.
+    fn selected_command(&self) -> Option<String> {
+        if !self.marked.is_empty() {
+            Some(self.marked.join(self.settings.selection_join.separator()))
+        } else {
+            self.matches.get(self.selection).map(|c| c.cmd.clone())
+        }
+    }
+
     fn accept_selection(&mut self) {
-        if !self.matches.is_empty() {
-            self.input.set(&self.matches[self.selection].cmd);
+        if let Some(command) = self.selected_command() {
+            self.input.set(&command);
+        }
+    }
+
+    fn copy_selection(&mut self) {
+        let command = self
+            .selected_command()
+            .unwrap_or_else(|| self.input.command.clone());
+        if command.is_empty() {
+            return;
+        }
+
+        self.flash = Some(match clipboard::copy(&mut stdout(), &command) {
+            Ok(()) => String::from("Copied to clipboard"),
+            Err(err) => format!("Unable to copy to clipboard ({err})"),
+        });
+    }
+
+    /// Mark or unmark the selected result. Marked results are accepted together, in the order
+    /// they were marked.
+    fn toggle_mark(&mut self) {
+        if self.matches.is_empty() {
+            return;
+        }
+
+        let cmd = &self.matches[self.selection].cmd;
+        if let Some(index) = self.marked.iter().position(|m| m == cmd) {
+            self.marked.remove(index);
+        } else {
+            self.marked.push(cmd.clone());
+        }
+        self.move_selection(MoveSelection::Down);
+    }
+
+    fn mark_prefix(&self, command: &Command) -> &'static str {
+        if self.marked.is_empty() {
+            ""
+        } else if self.marked.contains(&command.cmd) {
+            "● "
+        } else {
+            "  "
+        }
+    }
+
+    /// Pin the selected result everywhere, or unpin it globally and in this directory if it is
+    /// already pinned.
+    fn toggle_pin(&mut self) {
+        let Some(command) = self.matches.get(self.selection) else {
+            return;
+        };
+
+        let cmd = command.cmd.clone();
+        if command.pinned {
+            self.history.unpin_here(&cmd, &self.settings.dir);
+            self.flash = Some(String::from("Unpinned"));
+        } else {
+            self.history.pin(&cmd, None);
+            self.flash = Some(String::from("Pinned"));
+        }
+        self.build_cache_table();
+        self.refresh_matches(false);
+        if let Some(index) = self.matches.iter().position(|c| c.cmd == cmd) {
+            self.selection = index;
+        }
+    }
+
+    fn pin_prefix(&self, command: &Command) -> &'static str {
+        if command.pinned {
+            "★ "
+        } else if self.matches.iter().any(|c| c.pinned) {
+            "  "
+        } else {
+            ""
+        }
+    }
+
+    /// Start editing the note attached to the selected result.
+    fn start_note(&mut self) {
+        if let Some(command) = self.matches.get(self.selection) {
+            self.note_input = CommandInput::from(command.note.clone().unwrap_or_default());
+            self.menu_mode = MenuMode::EditNote;
+        }
+    }
+
+    fn save_note(&mut self) {
+        if let Some(command) = self.matches.get(self.selection) {
+            let cmd = command.cmd.clone();
+            self.history.annotate(&cmd, &self.note_input.command);
+            self.build_cache_table();
+            self.refresh_matches(false);
+            if let Some(index) = self.matches.iter().position(|c| c.cmd == cmd) {
+                self.selection = index;
+            }
+        }
+        self.menu_mode = MenuMode::Normal;
+    }
+
+    /// Edit the note in the menubar. Enter saves it (an empty note removes it) and ESC cancels.
+    fn handle_note_keyevent(&mut self, event: KeyEvent) {
+        match event {
+            KeyEvent {
+                code: KeyCode::Enter | Char('\r' | '\n'),
+                ..
+            } => self.save_note(),
+            KeyEvent {
+                modifiers: KeyModifiers::CONTROL,
+                code: Char('c' | 'g'),
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Esc, ..
+            } => self.menu_mode = MenuMode::Normal,
+            KeyEvent {
+                modifiers: KeyModifiers::CONTROL,
+                code,
+                ..
+            } => match code {
+                Char('a') => self.note_input.move_cursor(Move::BOL),
+                Char('e') => self.note_input.move_cursor(Move::EOL),
+                Char('b') => self.note_input.move_cursor(Move::Backward),
+                Char('f') => self.note_input.move_cursor(Move::Forward),
+                Char('u') => self.note_input.delete(Move::BOL),
+                Char('k') => self.note_input.delete(Move::EOL),
+                Char('h') => self.note_input.delete(Move::Backward),
+                _ => {}
+            },
+            KeyEvent { code, .. } => match code {
+                KeyCode::Backspace => self.note_input.delete(Move::Backward),
+                KeyCode::Delete => self.note_input.delete(Move::Forward),
+                KeyCode::Left => self.note_input.move_cursor(Move::Backward),
+                KeyCode::Right => self.note_input.move_cursor(Move::Forward),
+                KeyCode::Home => self.note_input.move_cursor(Move::BOL),
+                KeyCode::End => self.note_input.move_cursor(Move::EOL),
+                Char(c) => self.note_input.insert(c),
+                _ => {}
+            },
         }
     }
 
@@ -404,49 +932,239 @@ impl<'a> Interface<'a> {
     fn delete_selection(&mut self) {
         if !self.matches.is_empty() {
             {
-                let command = &self.matches[self.selection];
-                history_cleaner::clean(self.settings, self.history, &command.cmd);
-                self.delete_requests.push(command.cmd.clone());
+                let cmd = self.matches[self.selection].cmd.clone();
+                let pins = self
+                    .history
+                    .pinned_commands()
+                    .into_iter()
+                    .filter(|pinned| pinned.cmd == cmd)
+                    .collect();
+                // Pinned commands are only deleted after confirmation, so force it.
+                history_cleaner::clean(self.history, &cmd, true);
+                self.deleted.push((cmd, pins));
             }
             self.build_cache_table();
             self.refresh_matches(false);
+
+            let allow_plain_chars =
+                matches!(self.settings.key_scheme, KeyScheme::Vim) && !self.in_vim_insert_mode;
+            if let Some(key) = self
+                .settings
+                .key_bindings
+                .label(Action::Undo, allow_plain_chars)
+            {
+                self.flash = Some(format!("Deleted{MENU_SEPARATOR}{key} - Undo"));
+            }
         }
     }
 
+    /// Restore the most recently deleted command, and its pins.
+    fn undo_delete(&mut self) {
+        let Some((cmd, pins)) = self.deleted.pop() else {
+            return;
+        };
+
+        self.history.restore_command(Some(&cmd));
+        for pinned in pins {
+            self.history.pin(&pinned.cmd, pinned.dir.as_deref());
+        }
+        self.build_cache_table();
+        self.refresh_matches(false);
+        if let Some(index) = self.matches.iter().position(|c| c.cmd == cmd) {
+            self.selection = index;
+        }
+        self.flash = Some(String::from("Restored"));
+    }
+
     fn refresh_matches(&mut self, reset_selection: bool) {
         if reset_selection {
             self.selection = 0;
         }
-        self.matches = self.history.find_matches(
-            &self.input.command,
-            self.settings.results as i16,
-            self.settings.fuzzy,
-            &self.result_sort,
-        );
+        if self.group_results {
+            self.matches = self.history.find_grouped_matches(
+                &self.input.command,
+                self.settings.results as i16,
+                self.settings.fuzzy,
+                &self.settings.case_mode,
+                &self.result_sort,
+            );
+            self.insert_expanded_variants();
+        } else {
+            self.matches = self.history.find_matches(
+                &self.input.command,
+                self.settings.results as i16,
+                self.settings.fuzzy,
+                &self.settings.case_mode,
+                &self.result_sort,
+            );
+        }
     }
 
-    fn switch_result_sort(&mut self) {
-        match self.result_sort {
-            ResultSort::Rank => self.result_sort = ResultSort::LastRun,
-            ResultSort::LastRun => self.result_sort = ResultSort::Rank,
+    /// Insert the other variants of the expanded template directly after its group row.
+    fn insert_expanded_variants(&mut self) {
+        let Some(cmd_tpl) = &self.expanded_template else {
+            return;
+        };
+
+        match self.matches.iter().position(|c| &c.cmd_tpl == cmd_tpl) {
+            Some(index) => {
+                let variants: Vec<Command> = self
+                    .history
+                    .find_template_variants(
+                        cmd_tpl,
+                        &self.input.command,
+                        self.settings.results as i16,
+                        self.settings.fuzzy,
+                        &self.settings.case_mode,
+                        &self.result_sort,
+                    )
+                    .into_iter()
+                    .filter(|c| c.cmd != self.matches[index].cmd)
+                    .collect();
+                self.matches.splice(index + 1..index + 1, variants);
+            }
+            None => self.expanded_template = None,
         }
     }
 
+    fn toggle_expansion(&mut self) {
+        if !self.group_results || self.matches.is_empty() {
+            return;
+        }
+
+        let cmd_tpl = self.matches[self.selection].cmd_tpl.clone();
+        if self.expanded_template.as_ref() == Some(&cmd_tpl) {
+            self.expanded_template = None;
+            if let Some(index) = self.matches.iter().position(|c| c.cmd_tpl == cmd_tpl) {
+                self.selection = index;
+            }
+        } else if self.matches[self.selection].variants > 1 {
+            self.expanded_template = Some(cmd_tpl);
+        } else {
+            return;
+        }
+        self.refresh_matches(false);
+    }
+
+    fn group_prefix(&self, command: &Command) -> String {
+        if !self.group_results {
+            return String::new();
+        }
+
+        let expanded = self.expanded_template.as_ref() == Some(&command.cmd_tpl);
+        if command.variants > 1 {
+            let marker = if expanded { '▾' } else { '▸' };
+            format!("{marker} ({}) ", command.variants)
+        } else if expanded {
+            String::from("    ")
+        } else {
+            String::from("  ")
+        }
+    }
+
+    fn switch_failure_filter(&mut self) {
+        self.failure_filter = match self.failure_filter {
+            FailureFilter::Show => FailureFilter::Hide,
+            FailureFilter::Hide => FailureFilter::Only,
+            FailureFilter::Only => FailureFilter::Show,
+        };
+        self.build_cache_table();
+    }
+
+    fn switch_result_grouping(&mut self) {
+        self.group_results = !self.group_results;
+        self.expanded_template = None;
+    }
+
+    fn switch_result_sort(&mut self) {
+        self.result_sort = match self.result_sort {
+            ResultSort::Rank => ResultSort::LastRun,
+            ResultSort::LastRun => ResultSort::Frequency,
+            ResultSort::Frequency => ResultSort::Frecency,
+            ResultSort::Frecency => ResultSort::RecentHere,
+            ResultSort::RecentHere => ResultSort::Rank,
+        };
+    }
+
     fn switch_result_filter(&mut self) {
         self.result_filter = match self.result_filter {
             ResultFilter::Global => ResultFilter::CurrentDirectory,
-            ResultFilter::CurrentDirectory => ResultFilter::Global,
+            ResultFilter::CurrentDirectory => ResultFilter::CurrentSession,
+            ResultFilter::CurrentSession => ResultFilter::Successful,
+            ResultFilter::Successful => ResultFilter::Global,
         };
         self.build_cache_table();
     }
 
+    /// Switch the terminal to raw mode and make room for the interface, either on the alternate
+    /// screen or inline below the current line.
+    fn enter_screen<W: Write>(&mut self, screen: &mut W) {
+        terminal::enable_raw_mode().unwrap();
+
+        let (_width, height): (u16, u16) = terminal::size().unwrap();
+        self.inline_rows = self.settings.interface_height.inline_rows(height);
+        if let Some(rows) = self.inline_rows {
+            (self.start_column, _) = Interface::cursor_position();
+
+            // Scroll the terminal, if needed, to make room below the current line.
+            for _ in 0..rows {
+                queue!(screen, Print("\n")).unwrap();
+            }
+            screen.flush().unwrap();
+            let (_column, row) = Interface::cursor_position();
+            self.origin = (row + 1).saturating_sub(rows);
+            queue!(screen, self.move_to(0, 0), Clear(ClearType::FromCursorDown)).unwrap();
+        } else {
+            queue!(screen, EnterAlternateScreen, Clear(ClearType::All)).unwrap();
+        }
+        if self.settings.mouse {
+            queue!(screen, EnableMouseCapture).unwrap();
+        }
+    }
+
+    /// Undo `enter_screen`, leaving the terminal as it was.
+    fn leave_screen<W: Write>(&self, screen: &mut W) {
+        if self.inline_rows.is_some() {
+            // Leave everything above the interface as it was, with the cursor back on the line
+            // the interface was opened from.
+            queue!(
+                screen,
+                self.move_to(0, 0),
+                Clear(ClearType::FromCursorDown),
+                cursor::MoveTo(self.start_column, self.origin.saturating_sub(1)),
+                cursor::Show
+            )
+            .unwrap();
+        } else {
+            queue!(
+                screen,
+                Clear(ClearType::All),
+                cursor::Show,
+                LeaveAlternateScreen
+            )
+            .unwrap();
+        }
+        if self.settings.mouse {
+            queue!(screen, DisableMouseCapture).unwrap();
+        }
+        screen.flush().unwrap();
+        terminal::disable_raw_mode().unwrap();
+    }
+
     fn select(&mut self) {
+        // crossterm leaves colors out by itself when NO_COLOR is set, but in a way that also
+        // resets text attributes such as the selection's reverse video. So every color is the
+        // terminal default instead.
+        if self.settings.no_color {
+            Colored::set_ansi_color_disabled(false);
+        }
+
         let mut screen = stdout();
-        terminal::enable_raw_mode().unwrap();
-        queue!(screen, EnterAlternateScreen, Clear(ClearType::All)).unwrap();
+        self.enter_screen(&mut screen);
 
         self.refresh_matches(true);
         self.results(&mut screen);
+        self.preview(&mut screen);
         self.menubar(&mut screen);
         self.prompt(&mut screen);
 
@@ -455,17 +1173,31 @@ impl<'a> Interface<'a> {
         loop {
             let event =
                 read().unwrap_or_else(|e| panic!("McFly error: failed to read input {:?}", &e));
+
+            // Mouse movement doesn't change anything, so don't redraw for it.
+            if let Event::Mouse(MouseEvent {
+                kind: MouseEventKind::Moved | MouseEventKind::Drag(_),
+                ..
+            }) = event
+            {
+                continue;
+            }
             self.debug_cursor(&mut screen);
+            self.flash = None;
 
             match self.menu_mode {
                 MenuMode::Normal => {
-                    let early_out = match self.settings.key_scheme {
-                        KeyScheme::Emacs => self.select_with_emacs_key_scheme(event),
-                        KeyScheme::Vim => {
-                            if let Event::Key(key_event) = event {
-                                self.select_with_vim_key_scheme(key_event)
-                            } else {
-                                false
+                    let early_out = if let Event::Mouse(mouse_event) = event {
+                        self.handle_mouse_event(mouse_event)
+                    } else {
+                        match self.settings.key_scheme {
+                            KeyScheme::Emacs => self.select_with_emacs_key_scheme(event),
+                            KeyScheme::Vim => {
+                                if let Event::Key(key_event) = event {
+                                    self.select_with_vim_key_scheme(key_event)
+                                } else {
+                                    false
+                                }
                             }
                         }
                     };
@@ -502,22 +1234,59 @@ impl<'a> Interface<'a> {
                         }
                     }
                 }
+                MenuMode::EditNote => match event {
+                    Event::Key(key_event) => self.handle_note_keyevent(key_event),
+                    Event::Paste(s) => {
+                        for c in s.chars().filter(|c| *c != '\n') {
+                            self.note_input.insert(c);
+                        }
+                    }
+                    _ => {}
+                },
+            }
+
+            self.results(&mut screen);
+            self.preview(&mut screen);
+            self.menubar(&mut screen);
+            self.prompt(&mut screen);
+            screen.flush().unwrap();
+        }
+
+        self.leave_screen(&mut screen);
+    }
+
+    fn handle_mouse_event(&mut self, event: MouseEvent) -> bool {
+        let Some(row) = event.row.checked_sub(self.origin) else {
+            return false;
+        };
+
+        match event.kind {
+            MouseEventKind::ScrollUp => self.move_selection(MoveSelection::Up),
+            MouseEventKind::ScrollDown => self.move_selection(MoveSelection::Down),
+            MouseEventKind::Down(MouseButton::Left) => {
+                if row == self.info_line_index() {
+                    if !self.settings.disable_menu
+                        && let Some(action) = self.menu_action_at(event.column)
+                    {
+                        return self.perform_action(action);
+                    }
+                } else if let Some(index) = self.match_index_at(event.column, row) {
+                    let now = Instant::now();
+                    let double_click = self.last_click.is_some_and(|(at, clicked)| {
+                        clicked == index && now.duration_since(at) < DOUBLE_CLICK_INTERVAL
+                    });
+                    self.selection = index;
+                    if double_click {
+                        self.last_click = None;
+                        return self.perform_action(Action::Accept);
+                    }
+                    self.last_click = Some((now, index));
+                }
             }
-
-            self.results(&mut screen);
-            self.menubar(&mut screen);
-            self.prompt(&mut screen);
-            screen.flush().unwrap();
+            _ => {}
         }
 
-        queue!(
-            screen,
-            Clear(ClearType::All),
-            cursor::Show,
-            LeaveAlternateScreen
-        )
-        .unwrap();
-        terminal::disable_raw_mode().unwrap();
+        false
     }
 
     fn select_with_emacs_key_scheme(&mut self, event: Event) -> bool {
@@ -534,33 +1303,193 @@ impl<'a> Interface<'a> {
         }
     }
 
-    fn handle_emacs_keyevent(&mut self, event: KeyEvent) -> bool {
-        if event.kind != KeyEventKind::Press {
-            return false;
-        }
-        match event {
-            KeyEvent {
-                code: KeyCode::Enter | Char('\r' | '\n'),
-                ..
+    /// Perform an action bound in `Settings::key_bindings`. Returns true when the interface
+    /// should exit.
+    fn perform_action(&mut self, action: Action) -> bool {
+        match action {
+            Action::Accept => {
+                self.run = !self.settings.disable_run_command;
+                self.accept_selection();
+                return true;
             }
-            | KeyEvent {
-                modifiers: KeyModifiers::CONTROL,
-                code: Char('j'),
-                ..
-            } => {
+            Action::AcceptInDirectory => {
+                if self.marked.is_empty() {
+                    self.accept_dir = self
+                        .matches
+                        .get(self.selection)
+                        .and_then(|command| command.dir.clone())
+                        .filter(|dir| *dir != self.settings.dir);
+                }
                 self.run = !self.settings.disable_run_command;
                 self.accept_selection();
                 return true;
             }
-
-            KeyEvent {
-                code: KeyCode::Tab, ..
-            } => {
+            Action::Edit => {
                 self.run = false;
                 self.accept_selection();
                 return true;
             }
+            Action::Exit => {
+                self.run = false;
+                self.input.clear();
+                return true;
+            }
+            Action::Delete => {
+                if !self.matches.is_empty() {
+                    let pinned = self.history.is_pinned(&self.matches[self.selection].cmd);
+                    if self.settings.delete_without_confirm && !pinned {
+                        self.delete_selection();
+                    } else {
+                        self.menu_mode = MenuMode::ConfirmDelete;
+                    }
+                }
+            }
+            Action::ToggleSort => {
+                self.switch_result_sort();
+                self.refresh_matches(true);
+            }
+            Action::ToggleFilter => {
+                self.switch_result_filter();
+                self.refresh_matches(true);
+            }
+            Action::ToggleGrouping => {
+                self.switch_result_grouping();
+                self.refresh_matches(true);
+            }
+            Action::ToggleFailures => {
+                self.switch_failure_filter();
+                self.refresh_matches(true);
+            }
+            Action::TogglePreview => self.toggle_preview(),
+            Action::ToggleMark => self.toggle_mark(),
+            Action::TogglePin => self.toggle_pin(),
+            Action::Annotate => self.start_note(),
+            Action::Undo => self.undo_delete(),
+            Action::OpenEditor => return self.open_in_editor(),
+            Action::Copy => self.copy_selection(),
+            Action::InsertNewline => {
+                self.input.insert('\n');
+                self.refresh_matches(true);
+            }
+            Action::Up => self.move_selection(MoveSelection::Up),
+            Action::Down => self.move_selection(MoveSelection::Down),
+            Action::None => {}
+        }
+
+        false
+    }
+
+    /// Handle readline's ctrl-x ctrl-e, returning whether the interface should exit if the event
+    /// was part of it.
+    fn handle_ctrl_x_sequence(&mut self, event: &KeyEvent) -> Option<bool> {
+        let is_ctrl = |c| event.modifiers == KeyModifiers::CONTROL && event.code == Char(c);
+        if self.pending_ctrl_x {
+            self.pending_ctrl_x = false;
+            if is_ctrl('e') {
+                return Some(self.perform_action(Action::OpenEditor));
+            }
+        } else if is_ctrl('x') {
+            self.pending_ctrl_x = true;
+            return Some(false);
+        }
+        None
+    }
+
+    /// Edit the selected command, or the search if nothing matches, in `$VISUAL` or `$EDITOR`.
+    /// Returns true to exit with the edited command, or false if the editor failed.
+    fn open_in_editor(&mut self) -> bool {
+        let search = self.input.command.clone();
+        self.accept_selection();
+
+        let path = match Self::create_edit_file(&format!("{}\n", self.input.command)) {
+            Ok(path) => path,
+            Err(err) => {
+                self.input.set(&search);
+                self.flash = Some(format!("Unable to create a file to edit ({err})"));
+                return false;
+            }
+        };
+
+        let editor = env::var("VISUAL")
+            .or_else(|_| env::var("EDITOR"))
+            .unwrap_or_else(|_| String::from("vi"));
+        let mut words = editor.split_whitespace();
+        let program = words.next().unwrap_or("vi");
+
+        let mut screen = stdout();
+        self.leave_screen(&mut screen);
+        let status = process::Command::new(program)
+            .args(words)
+            .arg(&path)
+            .status();
+        self.enter_screen(&mut screen);
+
+        let edited = match status {
+            Ok(status) if status.success() => fs::read_to_string(&path)
+                .map_err(|err| format!("Unable to read the edited file ({err})")),
+            Ok(status) => Err(match status.code() {
+                Some(code) => format!("Edit discarded, {program} exited with code {code}"),
+                None => format!("Edit discarded, {program} was stopped by a signal"),
+            }),
+            Err(err) => Err(format!("Unable to run {program} ({err})")),
+        };
+        let _ = fs::remove_file(&path);
+
+        match edited {
+            Ok(edited) => {
+                self.input.set(edited.trim_end_matches(['\n', '\r']));
+                self.run = false;
+                true
+            }
+            Err(message) => {
+                self.input.set(&search);
+                self.flash = Some(message);
+                false
+            }
+        }
+    }
+
+    /// Create a file in the temp directory holding `contents`, with a random name and readable
+    /// only by the user, since the command may contain secrets.
+    fn create_edit_file(contents: &str) -> io::Result<PathBuf> {
+        let mut rng = rand::rng();
+        for _ in 0..16 {
+            let path = env::temp_dir().join(format!("mcfly-edit-{:016x}.sh", rng.random::<u64>()));
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            match options.open(&path) {
+                Ok(mut file) => {
+                    return match file.write_all(contents.as_bytes()) {
+                        Ok(()) => Ok(path),
+                        Err(err) => {
+                            let _ = fs::remove_file(&path);
+                            Err(err)
+                        }
+                    };
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "no unused file name",
+        ))
+    }
 
+    fn handle_emacs_keyevent(&mut self, event: KeyEvent) -> bool {
+        if event.kind != KeyEventKind::Press {
+            return false;
+        }
+        if let Some(early_out) = self.handle_ctrl_x_sequence(&event) {
+            return early_out;
+        }
+        if let Some(action) = self.settings.key_bindings.action_for(&event, false) {
+            return self.perform_action(action);
+        }
+        match event {
             KeyEvent {
                 modifiers: KeyModifiers::CONTROL,
                 code: Char('c' | 'g' | 'z' | 'r'),
@@ -640,7 +1569,7 @@ impl<'a> Interface<'a> {
             KeyEvent {
                 code: KeyCode::Right,
                 ..
-            } => self.input.move_cursor(Move::Forward),
+            } => self.move_right(),
 
             KeyEvent {
                 code: KeyCode::Up | KeyCode::PageUp,
@@ -682,34 +1611,6 @@ impl<'a> Interface<'a> {
                 self.refresh_matches(true);
             }
 
-            KeyEvent {
-                code: KeyCode::F(1),
-                ..
-            } => {
-                self.switch_result_sort();
-                self.refresh_matches(true);
-            }
-
-            KeyEvent {
-                code: KeyCode::F(2),
-                ..
-            } => {
-                if !self.matches.is_empty() {
-                    if self.settings.delete_without_confirm {
-                        self.delete_selection();
-                    } else {
-                        self.menu_mode = MenuMode::ConfirmDelete;
-                    }
-                }
-            }
-
-            KeyEvent {
-                code: KeyCode::F(3),
-                ..
-            } => {
-                self.switch_result_filter();
-                self.refresh_matches(true);
-            }
             _ => {}
         }
 
@@ -720,30 +1621,20 @@ impl<'a> Interface<'a> {
         if event.kind != KeyEventKind::Press {
             return false;
         }
+        if self.in_vim_insert_mode
+            && let Some(early_out) = self.handle_ctrl_x_sequence(&event)
+        {
+            return early_out;
+        }
+        if let Some(action) = self
+            .settings
+            .key_bindings
+            .action_for(&event, !self.in_vim_insert_mode)
+        {
+            return self.perform_action(action);
+        }
         if self.in_vim_insert_mode {
             match event {
-                KeyEvent {
-                    code: KeyCode::Tab, ..
-                } => {
-                    self.run = false;
-                    self.accept_selection();
-                    return true;
-                }
-
-                KeyEvent {
-                    code: KeyCode::Enter,
-                    ..
-                }
-                | KeyEvent {
-                    modifiers: KeyModifiers::CONTROL,
-                    code: Char('j'),
-                    ..
-                } => {
-                    self.run = !self.settings.disable_run_command;
-                    self.accept_selection();
-                    return true;
-                }
-
                 KeyEvent {
                     modifiers: KeyModifiers::CONTROL,
                     code: Char('c' | 'g' | 'z' | 'r'),
@@ -761,7 +1652,7 @@ impl<'a> Interface<'a> {
                 KeyEvent {
                     code: KeyCode::Right,
                     ..
-                } => self.input.move_cursor(Move::Forward),
+                } => self.move_right(),
 
                 KeyEvent {
                     code: KeyCode::Up | KeyCode::PageUp,
@@ -811,58 +1702,10 @@ impl<'a> Interface<'a> {
                     self.input.insert(c);
                     self.refresh_matches(true);
                 }
-                KeyEvent {
-                    code: KeyCode::F(1),
-                    ..
-                } => {
-                    self.switch_result_sort();
-                    self.refresh_matches(true);
-                }
-                KeyEvent {
-                    code: KeyCode::F(2),
-                    ..
-                } => {
-                    if !self.matches.is_empty() {
-                        if self.settings.delete_without_confirm {
-                            self.delete_selection();
-                        } else {
-                            self.menu_mode = MenuMode::ConfirmDelete;
-                        }
-                    }
-                }
-                KeyEvent {
-                    code: KeyCode::F(3),
-                    ..
-                } => {
-                    self.switch_result_filter();
-                    self.refresh_matches(true);
-                }
                 _ => {}
             }
         } else {
             match event {
-                KeyEvent {
-                    code: KeyCode::Tab, ..
-                } => {
-                    self.run = false;
-                    self.accept_selection();
-                    return true;
-                }
-
-                KeyEvent {
-                    code: KeyCode::Enter,
-                    ..
-                }
-                | KeyEvent {
-                    modifiers: KeyModifiers::CONTROL,
-                    code: Char('j'),
-                    ..
-                } => {
-                    self.run = !self.settings.disable_run_command;
-                    self.accept_selection();
-                    return true;
-                }
-
                 KeyEvent {
                     modifiers: KeyModifiers::CONTROL,
                     code: Char('c' | 'g' | 'z' | 'r'),
@@ -883,7 +1726,7 @@ impl<'a> Interface<'a> {
                 KeyEvent {
                     code: KeyCode::Right | Char('l'),
                     ..
-                } => self.input.move_cursor(Move::Forward),
+                } => self.move_right(),
 
                 KeyEvent {
                     code: KeyCode::Up | KeyCode::PageUp | Char('k'),
@@ -947,32 +1790,6 @@ impl<'a> Interface<'a> {
                     code: KeyCode::End, ..
                 } => self.input.move_cursor(Move::EOL),
 
-                KeyEvent {
-                    code: KeyCode::F(1),
-                    ..
-                } => {
-                    self.switch_result_sort();
-                    self.refresh_matches(true);
-                }
-                KeyEvent {
-                    code: KeyCode::F(2),
-                    ..
-                } => {
-                    if !self.matches.is_empty() {
-                        if self.settings.delete_without_confirm {
-                            self.delete_selection();
-                        } else {
-                            self.menu_mode = MenuMode::ConfirmDelete;
-                        }
-                    }
-                }
-                KeyEvent {
-                    code: KeyCode::F(3),
-                    ..
-                } => {
-                    self.switch_result_filter();
-                    self.refresh_matches(true);
-                }
                 _ => {}
             }
         }
@@ -980,36 +1797,73 @@ impl<'a> Interface<'a> {
         false
     }
 
+    /// Render `command` for the results, with its matches in `highlight_color`, or underlined
+    /// when `no_color` is set. Each char is otherwise shown in its color from `syntax_colors`,
+    /// or in `base_color` without one.
+    #[allow(clippy::too_many_arguments)]
     fn truncate_for_display(
         command: &Command,
+        prefix: &str,
         width: u16,
+        columns_width: u16,
         highlight_color: Color,
         base_color: Color,
+        syntax_colors: &[Color],
+        no_color: bool,
         debug: bool,
     ) -> String {
         let debug_space = if debug { 90 } else { 0 };
-        let max_grapheme_length = if width > debug_space {
-            width - debug_space - 9
+        let max_grapheme_length = if width > debug_space + columns_width {
+            width - debug_space - columns_width
         } else {
             11
         };
         let mut out = FixedLengthGraphemeString::empty(max_grapheme_length);
 
+        if !prefix.is_empty() {
+            execute!(out, SetForegroundColor(base_color)).unwrap();
+            out.push_grapheme_str(prefix);
+        }
+
         let mut match_indices = command.match_indices.iter().peekable();
 
-        for (i, c) in command.cmd.char_indices() {
-            match match_indices.peek() {
-                Some(&&j) if i == j => {
-                    let _ = match_indices.next();
-                    execute!(out, SetForegroundColor(highlight_color)).unwrap();
-                    out.push_grapheme_str(c);
-                }
-                _ => {
-                    execute!(out, SetForegroundColor(base_color)).unwrap();
-                    out.push_grapheme_str(c);
-                }
+        for (n, (i, c)) in command.cmd.char_indices().enumerate() {
+            let is_match = match_indices.next_if_eq(&&i).is_some();
+            let color = if is_match {
+                highlight_color
+            } else {
+                syntax_colors.get(n).copied().unwrap_or(base_color)
+            };
+            execute!(out, SetForegroundColor(color)).unwrap();
+            if no_color {
+                let underline = if is_match {
+                    Attribute::Underlined
+                } else {
+                    Attribute::NoUnderline
+                };
+                execute!(out, SetAttribute(underline)).unwrap();
+            }
+            // Multi-line commands are shown on one line, with a marker for each newline.
+            if c == '\n' {
+                out.push_grapheme_str(NEWLINE_MARKER);
+            } else {
+                out.push_grapheme_str(c);
             }
         }
+        if no_color {
+            execute!(out, SetAttribute(Attribute::NoUnderline)).unwrap();
+        }
+
+        if let Some(note) = &command.note {
+            execute!(
+                out,
+                SetForegroundColor(base_color),
+                SetAttribute(Attribute::Dim)
+            )
+            .unwrap();
+            out.push_grapheme_str(format!("  # {}", note.replace('\n', " ")));
+            execute!(out, SetAttribute(Attribute::NormalIntensity)).unwrap();
+        }
 
         if debug {
             out.max_grapheme_length += debug_space;
@@ -1048,7 +1902,7 @@ impl<'a> Interface<'a> {
     }
 
     fn result_top_index(&self) -> u16 {
-        let (_width, height): (u16, u16) = terminal::size().unwrap();
+        let (_width, height): (u16, u16) = self.size();
 
         if self.is_screen_view_bottom() {
             return height - RESULTS_TOP_INDEX;
@@ -1057,7 +1911,7 @@ impl<'a> Interface<'a> {
     }
 
     fn prompt_line_index(&self) -> u16 {
-        let (_width, height): (u16, u16) = terminal::size().unwrap();
+        let (_width, height): (u16, u16) = self.size();
         if self.is_screen_view_bottom() {
             return height - PROMPT_LINE_INDEX;
         }
@@ -1065,7 +1919,7 @@ impl<'a> Interface<'a> {
     }
 
     fn info_line_index(&self) -> u16 {
-        let (_width, height): (u16, u16) = terminal::size().unwrap();
+        let (_width, height): (u16, u16) = self.size();
         if self.is_screen_view_bottom() {
             return height;
         }
@@ -1079,6 +1933,114 @@ impl<'a> Interface<'a> {
         index
     }
 
+    fn results_width(&self, width: u16) -> u16 {
+        if self.show_preview && self.settings.preview_position == PreviewPosition::Side {
+            width / 2
+        } else {
+            width
+        }
+    }
+
+    fn preview_height(&self, height: u16) -> u16 {
+        if self.show_preview && self.settings.preview_position == PreviewPosition::Bottom {
+            height / 3
+        } else {
+            0
+        }
+    }
+
+    /// The column, row, width and height of the preview pane.
+    fn preview_area(&self) -> (u16, u16, u16, u16) {
+        let (width, height): (u16, u16) = self.size();
+        match self.settings.preview_position {
+            PreviewPosition::Side => {
+                let x = self.results_width(width) + 1;
+                if self.is_screen_view_bottom() {
+                    (x, 0, width - x, height - RESULTS_TOP_INDEX + 1)
+                } else {
+                    (x, RESULTS_TOP_INDEX, width - x, height - RESULTS_TOP_INDEX)
+                }
+            }
+            PreviewPosition::Bottom => {
+                let preview_height = self.preview_height(height);
+                if self.is_screen_view_bottom() {
+                    (0, 0, width, preview_height)
+                } else {
+                    (0, height - preview_height, width, preview_height)
+                }
+            }
+        }
+    }
+
+    /// The number of results that fit on screen.
+    fn result_height(&self) -> usize {
+        let (_width, height): (u16, u16) = self.size();
+        (height.saturating_sub(RESULTS_TOP_INDEX) as usize
+            + usize::from(self.is_screen_view_bottom()))
+        .saturating_sub(self.preview_height(height) as usize)
+    }
+
+    /// The index in `matches` of the first result on screen.
+    fn view_offset(&self, result_height: usize) -> usize {
+        if self.selection < result_height {
+            0
+        } else {
+            self.selection - result_height + 1
+        }
+    }
+
+    /// The index in `matches` of the result drawn at the given column and row, if any.
+    fn match_index_at(&self, column: u16, row: u16) -> Option<usize> {
+        let (width, _height): (u16, u16) = self.size();
+        if column >= self.results_width(width) {
+            return None;
+        }
+
+        let result_top_index = self.result_top_index();
+        let line = if self.is_screen_view_bottom() {
+            result_top_index.checked_sub(row)?
+        } else {
+            row.checked_sub(result_top_index)?
+        } as usize;
+
+        let result_height = self.result_height();
+        if line >= result_height {
+            return None;
+        }
+        let index = self.view_offset(result_height) + line;
+        (index < self.matches.len()).then_some(index)
+    }
+
+    /// The action of the menubar item drawn at the given column, if any.
+    fn menu_action_at(&self, column: u16) -> Option<Action> {
+        let separator_width = MENU_SEPARATOR.graphemes(true).count();
+        let mut start = 1;
+        for (text, action) in self.menu_mode.items(self) {
+            let end = start + text.graphemes(true).count();
+            if (start..end).contains(&(column as usize)) {
+                return action;
+            }
+            start = end + separator_width;
+        }
+        None
+    }
+
+    /// The width of the terminal and the height of the area the interface is drawn in.
+    fn size(&self) -> (u16, u16) {
+        let (width, height): (u16, u16) = terminal::size().unwrap();
+        (width, self.inline_rows.unwrap_or(height))
+    }
+
+    fn cursor_position() -> (u16, u16) {
+        cursor::position()
+            .unwrap_or_else(|err| panic!("McFly error: unable to get cursor position ({err})"))
+    }
+
+    /// Move to a column and row relative to the top of the interface.
+    fn move_to(&self, x: u16, y: u16) -> cursor::MoveTo {
+        cursor::MoveTo(x, y + self.origin)
+    }
+
     fn is_screen_view_bottom(&self) -> bool {
         self.settings.interface_view == InterfaceView::Bottom
     }
@@ -1098,3 +2060,73 @@ impl<'a> Interface<'a> {
 // Ctrl('q') | Ctrl('v') => quoted insert
 // Ctrl('y') => yank
 // Ctrl('_') => undo
+
+/// How long ago `last_run` was, in at most two units, such as `3d 4h`.
+fn relative_time(last_run: i64) -> String {
+    format_duration(
+        Duration::minutes(
+            Utc::now()
+                .signed_duration_since(Utc.timestamp_opt(last_run, 0).unwrap())
+                .num_minutes(),
+        )
+        .to_std()
+        .unwrap(),
+    )
+    .to_string()
+    .split(' ')
+    .take(2)
+    .map(|s| {
+        s.replace("years", "y")
+            .replace("year", "y")
+            .replace("months", "mo")
+            .replace("month", "mo")
+            .replace("days", "d")
+            .replace("day", "d")
+            .replace("hours", "h")
+            .replace("hour", "h")
+            .replace("minutes", "m")
+            .replace("minute", "m")
+            .replace("0s", "< 1m")
+    })
+    .collect::<Vec<String>>()
+    .join(" ")
+}
+
+/// Shorten `dir` to at most `max_len` characters, writing the home directory as `~` and keeping
+/// the end of the path when it is still too long.
+fn abbreviate_dir(dir: &str, home: Option<&str>, max_len: usize) -> String {
+    let dir = match home.and_then(|home| dir.strip_prefix(home.trim_end_matches('/'))) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("~{rest}"),
+        _ => dir.to_string(),
+    };
+
+    let len = dir.graphemes(true).count();
+    if len <= max_len {
+        return dir;
+    }
+
+    let tail: String = dir.graphemes(true).skip(len + 1 - max_len.max(1)).collect();
+    format!("…{tail}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::abbreviate_dir;
+
+    #[test]
+    fn abbreviate_dir_shortens_home_and_long_paths() {
+        assert_eq!(
+            abbreviate_dir("/home/me/src", Some("/home/me"), 20),
+            "~/src"
+        );
+        assert_eq!(abbreviate_dir("/home/me", Some("/home/me/"), 20), "~");
+        assert_eq!(
+            abbreviate_dir("/home/meg", Some("/home/me"), 20),
+            "/home/meg"
+        );
+        assert_eq!(
+            abbreviate_dir("/srv/projects/mcfly/src", None, 10),
+            "…mcfly/src"
+        );
+    }
+}
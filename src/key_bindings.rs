@@ -0,0 +1,348 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Named interface actions that can be bound to key chords in the `[keys]` section of `config.toml`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Accept,
+    /// Accept, running the command in the directory it was originally run in.
+    AcceptInDirectory,
+    Edit,
+    Exit,
+    Delete,
+    /// Restore the command deleted most recently during this search.
+    Undo,
+    ToggleSort,
+    ToggleFilter,
+    ToggleGrouping,
+    ToggleFailures,
+    TogglePreview,
+    ToggleMark,
+    /// Pin the selected command so that it always surfaces, or unpin it.
+    TogglePin,
+    /// Edit the note attached to the selected command.
+    Annotate,
+    /// Insert a newline into the search, for multi-line commands.
+    InsertNewline,
+    /// Edit the selected command in `$VISUAL` or `$EDITOR`.
+    OpenEditor,
+    /// Copy the selected command to the system clipboard.
+    Copy,
+    Up,
+    Down,
+    /// Disables a default binding.
+    None,
+}
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        match name.to_lowercase().as_str() {
+            "accept" | "run" => Some(Action::Accept),
+            "accept-in-directory" => Some(Action::AcceptInDirectory),
+            "edit" => Some(Action::Edit),
+            "exit" => Some(Action::Exit),
+            "delete" => Some(Action::Delete),
+            "undo" => Some(Action::Undo),
+            "toggle-sort" => Some(Action::ToggleSort),
+            "toggle-filter" => Some(Action::ToggleFilter),
+            "toggle-grouping" => Some(Action::ToggleGrouping),
+            "toggle-failures" => Some(Action::ToggleFailures),
+            "toggle-preview" => Some(Action::TogglePreview),
+            "toggle-mark" => Some(Action::ToggleMark),
+            "toggle-pin" => Some(Action::TogglePin),
+            "annotate" => Some(Action::Annotate),
+            "insert-newline" => Some(Action::InsertNewline),
+            "open-editor" => Some(Action::OpenEditor),
+            "copy" => Some(Action::Copy),
+            "up" => Some(Action::Up),
+            "down" => Some(Action::Down),
+            "none" => Some(Action::None),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    /// Parse chords such as `f1`, `ctrl-s`, `alt-d`, `ctrl-space`, `enter` or `g`.
+    pub fn parse(chord: &str) -> Option<KeyChord> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = chord.trim();
+
+        // A trailing `-` is the minus key itself, as in `ctrl--`.
+        while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+            rest = key;
+        }
+
+        let code = match rest.to_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "esc" | "escape" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            lower => {
+                if let Some(n) = lower
+                    .strip_prefix('f')
+                    .and_then(|n| n.parse::<u8>().ok())
+                    .filter(|n| (1..=24).contains(n))
+                {
+                    KeyCode::F(n)
+                } else {
+                    let mut chars = rest.chars();
+                    match (chars.next(), chars.next()) {
+                        // Keep the case of plain characters so that `G` and `g` can differ.
+                        (Some(c), None) if modifiers.is_empty() => KeyCode::Char(c),
+                        (Some(c), None) => KeyCode::Char(c.to_ascii_lowercase()),
+                        _ => return None,
+                    }
+                }
+            }
+        };
+
+        Some(KeyChord { code, modifiers })
+    }
+
+    fn matches(&self, event: &KeyEvent) -> bool {
+        // Terminals report shifted characters as the uppercase character, sometimes with SHIFT set.
+        let modifiers = match event.code {
+            KeyCode::Char(_) => event.modifiers - KeyModifiers::SHIFT,
+            _ => event.modifiers,
+        };
+        self.code == event.code && self.modifiers == modifiers
+    }
+
+    /// Whether this chord types a character, and so can only be bound in vim's normal mode.
+    fn is_plain_char(&self) -> bool {
+        matches!(self.code, KeyCode::Char(c) if !c.is_control()) && self.modifiers.is_empty()
+    }
+
+    /// A short label for the menubar.
+    pub fn label(&self) -> String {
+        let key = match self.code {
+            KeyCode::Enter => String::from("⏎"),
+            KeyCode::Tab => String::from("TAB"),
+            KeyCode::Esc => String::from("ESC"),
+            KeyCode::F(n) => format!("F{n}"),
+            KeyCode::Char(' ') => String::from("SPC"),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Up => String::from("↑"),
+            KeyCode::Down => String::from("↓"),
+            other => format!("{other:?}"),
+        };
+
+        let mut label = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            label.push_str("C-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            label.push_str("M-");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            label.push_str("S-");
+        }
+        label.push_str(&key);
+        label
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyBindings {
+    bindings: Vec<(KeyChord, Action)>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        let chord = |code, modifiers| KeyChord { code, modifiers };
+        KeyBindings {
+            bindings: vec![
+                (chord(KeyCode::Enter, KeyModifiers::NONE), Action::Accept),
+                // Some terminals report Enter as a raw carriage return or line feed.
+                (
+                    chord(KeyCode::Char('\r'), KeyModifiers::NONE),
+                    Action::Accept,
+                ),
+                (
+                    chord(KeyCode::Char('\n'), KeyModifiers::NONE),
+                    Action::Accept,
+                ),
+                (
+                    chord(KeyCode::Char('j'), KeyModifiers::CONTROL),
+                    Action::Accept,
+                ),
+                (chord(KeyCode::Tab, KeyModifiers::NONE), Action::Edit),
+                (
+                    chord(KeyCode::Char('o'), KeyModifiers::CONTROL),
+                    Action::AcceptInDirectory,
+                ),
+                (chord(KeyCode::F(1), KeyModifiers::NONE), Action::ToggleSort),
+                (chord(KeyCode::F(2), KeyModifiers::NONE), Action::Delete),
+                (
+                    chord(KeyCode::F(3), KeyModifiers::NONE),
+                    Action::ToggleFilter,
+                ),
+                (
+                    chord(KeyCode::F(4), KeyModifiers::NONE),
+                    Action::ToggleGrouping,
+                ),
+                (
+                    chord(KeyCode::F(5), KeyModifiers::NONE),
+                    Action::ToggleFailures,
+                ),
+                (
+                    chord(KeyCode::F(6), KeyModifiers::NONE),
+                    Action::TogglePreview,
+                ),
+                (chord(KeyCode::F(7), KeyModifiers::NONE), Action::TogglePin),
+                (chord(KeyCode::F(8), KeyModifiers::NONE), Action::Annotate),
+                (chord(KeyCode::F(9), KeyModifiers::NONE), Action::Undo),
+                (
+                    chord(KeyCode::Char(' '), KeyModifiers::CONTROL),
+                    Action::ToggleMark,
+                ),
+                (
+                    chord(KeyCode::Enter, KeyModifiers::ALT),
+                    Action::InsertNewline,
+                ),
+                // As in readline's vi mode. Emacs mode's ctrl-x ctrl-e is handled by Interface.
+                (
+                    chord(KeyCode::Char('v'), KeyModifiers::NONE),
+                    Action::OpenEditor,
+                ),
+                // As in emacs' kill-ring-save and vim's yank.
+                (chord(KeyCode::Char('w'), KeyModifiers::ALT), Action::Copy),
+                (chord(KeyCode::Char('y'), KeyModifiers::NONE), Action::Copy),
+                (chord(KeyCode::Char('u'), KeyModifiers::NONE), Action::Undo),
+            ],
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Bind `chord` to `action`, replacing any existing binding for the chord. Configured
+    /// bindings take precedence over the defaults when labelling the menubar.
+    pub fn bind(&mut self, chord: KeyChord, action: Action) {
+        self.bindings.retain(|(c, _)| *c != chord);
+        self.bindings.insert(0, (chord, action));
+    }
+
+    /// The action bound to `event`, if any. Plain characters are only considered when
+    /// `allow_plain_chars` is set, so that they can still be typed into the search.
+    pub fn action_for(&self, event: &KeyEvent, allow_plain_chars: bool) -> Option<Action> {
+        self.bindings
+            .iter()
+            .filter(|(chord, _)| allow_plain_chars || !chord.is_plain_char())
+            .find(|(chord, _)| chord.matches(event))
+            .map(|(_, action)| *action)
+    }
+
+    /// The menubar label of the chord bound to `action`, if it is bound at all.
+    pub fn label(&self, action: Action, allow_plain_chars: bool) -> Option<String> {
+        self.bindings
+            .iter()
+            .filter(|(chord, _)| allow_plain_chars || !chord.is_plain_char())
+            .find(|(_, a)| *a == action)
+            .map(|(chord, _)| chord.label())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, KeyBindings, KeyChord};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    #[test]
+    fn parse_chords() {
+        let chord = |code, modifiers| Some(KeyChord { code, modifiers });
+        assert_eq!(
+            KeyChord::parse("f1"),
+            chord(KeyCode::F(1), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyChord::parse("Ctrl-S"),
+            chord(KeyCode::Char('s'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            KeyChord::parse("ctrl-space"),
+            chord(KeyCode::Char(' '), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            KeyChord::parse("ctrl-alt-d"),
+            chord(
+                KeyCode::Char('d'),
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            )
+        );
+        assert_eq!(
+            KeyChord::parse("G"),
+            chord(KeyCode::Char('G'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyChord::parse("ctrl--"),
+            chord(KeyCode::Char('-'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(KeyChord::parse("hyper-x"), None);
+        assert_eq!(KeyChord::parse("f99"), None);
+    }
+
+    #[test]
+    fn configured_bindings_override_defaults() {
+        let mut bindings = KeyBindings::default();
+        bindings.bind(KeyChord::parse("ctrl-s").unwrap(), Action::ToggleSort);
+        bindings.bind(KeyChord::parse("f1").unwrap(), Action::None);
+        bindings.bind(KeyChord::parse("d").unwrap(), Action::Delete);
+
+        let f1 = KeyEvent::new(KeyCode::F(1), KeyModifiers::NONE);
+        let ctrl_s = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        let d = KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE);
+        assert_eq!(bindings.action_for(&f1, false), Some(Action::None));
+        assert_eq!(
+            bindings.action_for(&ctrl_s, false),
+            Some(Action::ToggleSort)
+        );
+        assert_eq!(bindings.action_for(&d, false), None);
+        assert_eq!(bindings.action_for(&d, true), Some(Action::Delete));
+        assert_eq!(
+            bindings.label(Action::ToggleSort, false),
+            Some(String::from("C-s"))
+        );
+        assert_eq!(
+            bindings.label(Action::Delete, true),
+            Some(String::from("d"))
+        );
+        assert_eq!(
+            bindings.label(Action::Delete, false),
+            Some(String::from("F2"))
+        );
+        assert_eq!(bindings.label(Action::Up, true), None);
+
+        let ctrl_j = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::CONTROL);
+        let carriage_return = KeyEvent::new(KeyCode::Char('\r'), KeyModifiers::NONE);
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(bindings.action_for(&ctrl_j, false), Some(Action::Accept));
+        assert_eq!(
+            bindings.action_for(&carriage_return, false),
+            Some(Action::Accept)
+        );
+        bindings.bind(KeyChord::parse("enter").unwrap(), Action::None);
+        assert_eq!(bindings.action_for(&enter, false), Some(Action::None));
+    }
+}
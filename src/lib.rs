@@ -1,19 +1,25 @@
 pub mod cli;
+pub mod clipboard;
 pub mod command_input;
 pub mod dumper;
 pub mod fake_typer;
 pub mod fixed_length_grapheme_string;
 pub mod history;
 pub mod history_cleaner;
+pub mod ignore_rules;
 pub mod init;
 pub mod interface;
+pub mod key_bindings;
 pub mod network;
 pub mod node;
 pub mod path_update_helpers;
+pub mod redaction;
 pub mod settings;
 pub mod shell_history;
+pub mod shell_syntax;
 pub mod simplified_command;
 pub mod stats_generator;
+pub mod theme;
 pub mod time;
 pub mod trainer;
 pub mod training_cache;
@@ -1,3 +1,5 @@
+use chrono::{Local, TimeZone};
+use std::env;
 use std::fs;
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -5,19 +7,54 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use mcfly::dumper::Dumper;
 use mcfly::fake_typer;
 use mcfly::history::History;
+use mcfly::history_cleaner;
 use mcfly::init::Init;
 use mcfly::interface::Interface;
-use mcfly::settings::Mode;
+use mcfly::redaction;
 use mcfly::settings::Settings;
+use mcfly::settings::{ConfigMode, Mode, RedactMode, SETTINGS, TrashMode, toml_value};
 use mcfly::shell_history;
 use mcfly::stats_generator::StatsGenerator;
+use mcfly::theme;
 use mcfly::trainer::Trainer;
 
 fn handle_addition(settings: &Settings) {
-    let history = History::load(settings.history_format);
-    if history.should_add(&settings.command) {
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
+
+    // History::add masks secrets itself; they are also masked here so that they don't show up
+    // in the dry-run message or get appended to the history file.
+    let secrets = settings
+        .active_redactor()
+        .map(|redactor| redactor.find(&settings.command))
+        .unwrap_or_default();
+    let command = redaction::mask(&settings.command, &secrets);
+
+    let ignore_reason = if !settings.record {
+        Some(String::from("recording is turned off"))
+    } else if settings.redact == RedactMode::Refuse && !secrets.is_empty() {
+        Some(format!(
+            "it looks like it contains a secret ({})",
+            redaction::detector_names(&secrets)
+        ))
+    } else {
+        history.ignore_reason(&command, &settings.dir, &settings.ignore_rules)
+    };
+
+    if settings.dry_run {
+        match ignore_reason {
+            Some(reason) => println!("McFly: Would not record '{command}', because {reason}"),
+            None => println!("McFly: Would record '{command}'"),
+        }
+        return;
+    }
+
+    if ignore_reason.is_none() {
         history.add(
-            &settings.command,
+            &command,
             &settings.session_id,
             &settings.dir,
             &settings.when_run,
@@ -28,7 +65,7 @@ fn handle_addition(settings: &Settings) {
         if let Some(append_to_histfile) = &settings.append_to_histfile {
             let histfile = PathBuf::from(append_to_histfile);
             let command = shell_history::HistoryCommand::new(
-                &settings.command,
+                &command,
                 settings.when_run.unwrap_or(
                     SystemTime::now()
                         .duration_since(UNIX_EPOCH)
@@ -40,10 +77,19 @@ fn handle_addition(settings: &Settings) {
             shell_history::append_history_entry(&command, &histfile, settings.debug);
         }
     }
+
+    // Housekeeping happens here rather than before a search, so that it never delays the
+    // interface.
+    history.purge_deleted_commands(Some(i64::from(settings.trash_days) * 24 * 60 * 60));
+    history.apply_retention(&settings.retention, &settings.ignore_rules);
 }
 
 fn handle_search(settings: &Settings) {
-    let history = History::load(settings.history_format);
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
     let result = Interface::new(settings, &history).display();
     if let Some(cmd) = result.selection {
         if let Some(path) = &settings.output_selection {
@@ -58,23 +104,35 @@ fn handle_search(settings: &Settings) {
                 out.push_str("mode display\n");
             }
 
-            // Next, the desired commandline selected by the user.
+            // Next, the desired commandline selected by the user. Values are escaped so that
+            // multi-line commandlines still fit on a single line of output.
             out.push_str("commandline ");
-            out.push_str(&cmd);
+            out.push_str(&escape_output_value(&cmd));
             out.push('\n');
 
+            // Then the directory to change to first, if the user asked to run the command where
+            // it was originally run.
+            if let Some(dir) = &result.dir {
+                out.push_str("cd ");
+                out.push_str(&escape_output_value(dir));
+                out.push('\n');
+            }
+
             // Finally, any requests for deletion of commands from shell history, for cases where
             // shells need to handle this natively instead of through us editing HISTFILE/MCFLY_HISTFILE.
             for delete_request in result.delete_requests {
                 out.push_str("delete ");
-                out.push_str(&delete_request);
+                out.push_str(&escape_output_value(&delete_request));
                 out.push('\n');
             }
 
             fs::write(path, &out)
                 .unwrap_or_else(|err| panic!("McFly error: unable to write to {path}: {err}"));
         } else {
-            fake_typer::use_tiocsti(&cmd);
+            match &result.dir {
+                Some(dir) => fake_typer::use_tiocsti(&format!("cd {} && {cmd}", shell_quote(dir))),
+                None => fake_typer::use_tiocsti(&cmd),
+            }
 
             if result.run {
                 fake_typer::use_tiocsti("\n");
@@ -83,27 +141,275 @@ fn handle_search(settings: &Settings) {
     }
 }
 
+/// Escape backslashes and newlines as `\\` and `\n`, which the shell scripts decode with `printf '%b'`.
+fn escape_output_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Quote `value` for POSIX shells.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
 fn handle_train(settings: &Settings) {
-    let mut history = History::load(settings.history_format);
+    let mut history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
     Trainer::new(settings, &mut history).train();
 }
 
 fn handle_move(settings: &Settings) {
-    let history = History::load(settings.history_format);
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
     history.update_paths(&settings.old_dir.clone().unwrap(), &settings.dir, true);
 }
 
+fn handle_pin(settings: &Settings) {
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
+    if settings.command.is_empty() {
+        for pinned in history.pinned_commands() {
+            match pinned.dir {
+                Some(dir) => println!("{}\t(in {dir})", pinned.cmd),
+                None => println!("{}", pinned.cmd),
+            }
+        }
+    } else {
+        history.pin(&settings.command, settings.pin_dir.as_deref());
+    }
+}
+
+fn handle_unpin(settings: &Settings) {
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
+    if history.unpin(&settings.command, settings.pin_dir.as_deref()) == 0 {
+        eprintln!("McFly: {} is not pinned", settings.command);
+        std::process::exit(1);
+    }
+}
+
+fn handle_annotate(settings: &Settings) {
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
+    match &settings.note {
+        Some(note) => history.annotate(&settings.command, note),
+        None => {
+            if let Some(note) = history.annotation(&settings.command) {
+                println!("{note}");
+            }
+        }
+    }
+}
+
+fn handle_trash(settings: &Settings) {
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
+    match settings.trash_mode {
+        TrashMode::List => {
+            for deleted in history.deleted_commands() {
+                let when = Local
+                    .timestamp_opt(deleted.deleted_at, 0)
+                    .single()
+                    .map(|when| when.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default();
+                println!("{when}  {:>4}  {}", deleted.count, deleted.cmd);
+            }
+        }
+        TrashMode::Restore => {
+            let command = (!settings.restore_all).then_some(settings.command.as_str());
+            let mut deleted: Vec<_> = history
+                .deleted_commands()
+                .into_iter()
+                .filter(|deleted| command.is_none_or(|command| deleted.cmd == command))
+                .collect();
+            let restored = history.restore_command(command);
+            if restored == 0 {
+                eprintln!("McFly: Nothing to restore");
+                std::process::exit(1);
+            }
+            // The shell's history file was cleaned when the command was deleted, so it gets the
+            // command back too, in the order the commands were last run.
+            deleted.sort_by_key(|deleted| deleted.last_run);
+            for deleted in &deleted {
+                history_cleaner::restore_shell_history(settings, &deleted.cmd, deleted.last_run);
+            }
+            println!("McFly: Restored {restored} run(s)");
+        }
+        TrashMode::Empty => {
+            let purged = history.purge_deleted_commands(None);
+            println!("McFly: Permanently deleted {purged} run(s)");
+        }
+    }
+}
+
+fn handle_prune(settings: &Settings) {
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
+    let report = history.prune(
+        &settings.prune_policy,
+        &settings.ignore_rules,
+        settings.dry_run,
+    );
+
+    if settings.dry_run {
+        for (cmd, runs) in &report.commands {
+            println!("{runs:>4}  {cmd}");
+        }
+        println!(
+            "McFly: Would delete {} run(s) of {} command(s)",
+            report.runs,
+            report.commands.len()
+        );
+    } else {
+        // Compact even if nothing was deleted, to reclaim what automatic retention freed.
+        let bytes_reclaimed = history.vacuum();
+        println!(
+            "McFly: Deleted {} run(s) of {} command(s), reclaiming {}",
+            report.runs,
+            report.commands.len(),
+            format_size(bytes_reclaimed)
+        );
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} bytes")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+fn handle_scan(settings: &Settings) {
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
+    let mut found = 0;
+    for (command, runs) in history.stored_commands() {
+        let secrets = settings.redactor.find(&command);
+        if secrets.is_empty() {
+            continue;
+        }
+        found += 1;
+        let redacted = redaction::mask(&command, &secrets);
+        println!(
+            "{runs:>4}  {redacted}  ({})",
+            redaction::detector_names(&secrets)
+        );
+        if settings.scan_redact {
+            history.redact_command(&command, &redacted);
+        }
+    }
+
+    if found == 0 {
+        println!("McFly: No secrets found");
+    } else if settings.scan_redact {
+        println!("McFly: Redacted {found} command(s)");
+    } else {
+        println!(
+            "McFly: Found {found} command(s) that look like they contain secrets. Run `mcfly scan --redact` to redact them."
+        );
+    }
+}
+
+fn handle_config(settings: &Settings) {
+    let key = settings.config_key.as_deref().unwrap_or_default();
+    let setting = SETTINGS.iter().find(|setting| setting.key == key);
+
+    match settings.config_mode {
+        ConfigMode::Show => {
+            println!("# {}", Settings::mcfly_config_path().display());
+            if let Some(project_config_path) = &settings.project_config_path {
+                println!("# {}", project_config_path.display());
+            }
+            for setting in &SETTINGS {
+                match settings.get(setting.key) {
+                    Some((value, source)) => {
+                        let line = format!("{} = {}", setting.key, toml_value(&value));
+                        println!("{line:<40} # {}", source.label(setting));
+                    }
+                    None => println!("# {} is not set", setting.key),
+                }
+            }
+        }
+        ConfigMode::Get => {
+            if setting.is_none() {
+                eprintln!("McFly: Unknown setting '{key}'");
+                std::process::exit(1);
+            }
+            if let Some((value, _source)) = settings.get(key) {
+                println!("{value}");
+            }
+        }
+        ConfigMode::Set => {
+            let value = settings.config_value.as_deref().unwrap_or_default();
+            match Settings::write_config_value(key, value) {
+                Ok(config_path) => {
+                    println!("McFly: Set {key} in {}", config_path.display());
+                    if let Some(setting) = setting
+                        && env::var(setting.env).is_ok()
+                    {
+                        println!("McFly: {} is set, and takes precedence", setting.env);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("McFly: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        ConfigMode::Path => println!("{}", Settings::mcfly_config_path().display()),
+    }
+}
+
 fn handle_init(settings: &Settings) {
     Init::new(&settings.init_mode);
 }
 
 fn handle_dump(settings: &Settings) {
-    let history = History::load(settings.history_format);
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
     Dumper::new(settings, &history).dump();
 }
 
 fn handle_stats(settings: &Settings) {
-    let history = History::load(settings.history_format);
+    let history = History::load(
+        settings.history_format,
+        &settings.ignore_rules,
+        settings.active_redactor(),
+    );
     let stats = StatsGenerator::new(&history).generate_stats(settings);
     println!("{stats}");
 }
@@ -111,7 +417,13 @@ fn handle_stats(settings: &Settings) {
 fn main() {
     let mut settings = Settings::parse_args();
 
-    settings.load_config();
+    // Only the interface needs colors, so only query the terminal when searching.
+    if matches!(settings.mode, Mode::Search)
+        && settings.auto_lightmode
+        && let Some(lightmode) = theme::terminal_is_light()
+    {
+        settings.lightmode = lightmode;
+    }
 
     match settings.mode {
         Mode::Add => {
@@ -126,6 +438,27 @@ fn main() {
         Mode::Move => {
             handle_move(&settings);
         }
+        Mode::Pin => {
+            handle_pin(&settings);
+        }
+        Mode::Unpin => {
+            handle_unpin(&settings);
+        }
+        Mode::Annotate => {
+            handle_annotate(&settings);
+        }
+        Mode::Trash => {
+            handle_trash(&settings);
+        }
+        Mode::Prune => {
+            handle_prune(&settings);
+        }
+        Mode::Scan => {
+            handle_scan(&settings);
+        }
+        Mode::Config => {
+            handle_config(&settings);
+        }
         Mode::Init => {
             handle_init(&settings);
         }
@@ -1,16 +1,22 @@
-use crate::cli::{Cli, DumpFormat, SortOrder, SubCommand};
+use crate::cli::{Cli, ConfigAction, DumpFormat, SortOrder, SubCommand, TrashAction};
+use crate::ignore_rules::{self, IgnoreRule};
+use crate::key_bindings::{Action, KeyBindings, KeyChord};
+use crate::path_update_helpers::normalize_path;
+use crate::redaction::Redactor;
 use crate::shell_history;
-use crate::time::parse_timestamp;
+use crate::theme;
+use crate::time::{parse_duration, parse_timestamp};
 use clap::Parser;
 use config::Source;
 use config::Value;
 use crossterm::style::Color;
 use directories_next::{ProjectDirs, UserDirs};
+use itertools::Itertools;
 use regex::Regex;
 use std::collections::HashMap;
 use std::env;
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
@@ -20,17 +26,39 @@ pub enum Mode {
     Search,
     Train,
     Move,
+    Pin,
+    Unpin,
+    Annotate,
+    Trash,
+    Prune,
+    Scan,
+    Config,
     Init,
     Dump,
     Stats,
 }
 
+#[derive(Debug)]
+pub enum ConfigMode {
+    Show,
+    Get,
+    Set,
+    Path,
+}
+
 #[derive(Debug)]
 pub enum KeyScheme {
     Emacs,
     Vim,
 }
 
+#[derive(Debug)]
+pub enum TrashMode {
+    List,
+    Restore,
+    Empty,
+}
+
 #[derive(Debug)]
 pub enum InitMode {
     Bash,
@@ -45,16 +73,240 @@ pub enum InterfaceView {
     Bottom,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceHeight {
+    /// Take over the whole terminal using the alternate screen.
+    Full,
+    /// Draw inline, below the shell prompt, using this many rows.
+    Rows(u16),
+    /// Draw inline, below the shell prompt, using this percentage of the terminal's rows.
+    Percent(u16),
+}
+
+impl InterfaceHeight {
+    pub fn parse(value: &str) -> Option<InterfaceHeight> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("full") {
+            return Some(InterfaceHeight::Full);
+        }
+        match value.strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<u16>().ok()? {
+                100.. => Some(InterfaceHeight::Full),
+                percent => Some(InterfaceHeight::Percent(percent)),
+            },
+            None => value.parse::<u16>().ok().map(InterfaceHeight::Rows),
+        }
+    }
+
+    /// The number of rows to draw inline in a terminal with `terminal_height` rows, or `None` to
+    /// use the full screen. At least one row is left for the shell prompt.
+    pub fn inline_rows(&self, terminal_height: u16) -> Option<u16> {
+        let rows = match *self {
+            InterfaceHeight::Full => return None,
+            InterfaceHeight::Rows(rows) => rows,
+            InterfaceHeight::Percent(percent) => {
+                (u32::from(terminal_height) * u32::from(percent) / 100) as u16
+            }
+        };
+        Some(
+            rows.max(MIN_INLINE_ROWS)
+                .min(terminal_height.saturating_sub(1)),
+        )
+        .filter(|rows| *rows >= MIN_INLINE_ROWS)
+    }
+}
+
+/// The prompt, menubar and a single result need this many rows.
+const MIN_INLINE_ROWS: u16 = 7;
+
+/// What a column beside each result shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    /// How long ago the command was last run.
+    RelativeTime,
+    /// When the command was last run.
+    Time,
+    /// ✓ or ✗ for the exit status of the last run.
+    Status,
+    /// How many times the command was run.
+    Runs,
+    /// The directory the command was run in, abbreviated to fit.
+    Dir,
+    /// A marker for commands run in the current session.
+    Session,
+}
+
+impl ColumnKind {
+    pub fn from_name(name: &str) -> Option<ColumnKind> {
+        match name.trim().to_lowercase().as_str() {
+            "relative-time" => Some(ColumnKind::RelativeTime),
+            "time" => Some(ColumnKind::Time),
+            "status" => Some(ColumnKind::Status),
+            "runs" => Some(ColumnKind::Runs),
+            "dir" => Some(ColumnKind::Dir),
+            "session" => Some(ColumnKind::Session),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            ColumnKind::RelativeTime => "relative-time",
+            ColumnKind::Time => "time",
+            ColumnKind::Status => "status",
+            ColumnKind::Runs => "runs",
+            ColumnKind::Dir => "dir",
+            ColumnKind::Session => "session",
+        }
+    }
+
+    fn default_width(self) -> u16 {
+        match self {
+            ColumnKind::RelativeTime => 9,
+            ColumnKind::Time => 17,
+            ColumnKind::Status => 2,
+            ColumnKind::Runs => 7,
+            ColumnKind::Dir => 24,
+            ColumnKind::Session => 2,
+        }
+    }
+}
+
+/// A column shown to the right of each result. Its text is right-aligned within `width`, which
+/// includes the space separating it from the column before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResultColumn {
+    pub kind: ColumnKind,
+    pub width: u16,
+    /// Overrides the column's default color.
+    pub color: Option<Color>,
+}
+
+impl ResultColumn {
+    #[must_use]
+    pub fn new(kind: ColumnKind) -> ResultColumn {
+        ResultColumn {
+            kind,
+            width: kind.default_width(),
+            color: None,
+        }
+    }
+
+    /// Parse a comma-separated list of column names, such as `status,runs,relative-time`.
+    #[must_use]
+    pub fn parse_list(value: &str) -> Vec<ResultColumn> {
+        value
+            .split(',')
+            .filter_map(ColumnKind::from_name)
+            .map(ResultColumn::new)
+            .collect()
+    }
+
+    /// Parse a column from `config.toml`, either as a name or as a table with `name`, and
+    /// optionally `width` and `color`.
+    fn from_config(value: Value) -> Option<ResultColumn> {
+        if let Ok(name) = value.clone().into_string() {
+            return ColumnKind::from_name(&name).map(ResultColumn::new);
+        }
+
+        let table = value.into_table().ok()?;
+        let kind = table
+            .get("name")
+            .and_then(|v| v.clone().into_string().ok())
+            .and_then(|v| ColumnKind::from_name(&v))?;
+        let mut column = ResultColumn::new(kind);
+        if let Some(width) = table
+            .get("width")
+            .and_then(|v| v.clone().into_uint().ok())
+            .and_then(|v| u16::try_from(v).ok())
+            .filter(|width| *width > 0)
+        {
+            column.width = width;
+        }
+        column.color = table
+            .get("color")
+            .and_then(|v| v.clone().into_string().ok())
+            .and_then(|v| theme::parse_color(&v));
+        Some(column)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PreviewPosition {
+    /// To the right of the results.
+    Side,
+    /// On the far side of the results from the prompt.
+    Bottom,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ResultSort {
     Rank,
     LastRun,
+    /// Number of times the command was run.
+    Frequency,
+    /// Number of runs, weighted towards recent ones.
+    Frecency,
+    /// Last time the command was run in the current directory.
+    RecentHere,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ResultFilter {
     Global,
+    /// Commands run in the current directory or any of its subdirectories.
     CurrentDirectory,
+    /// Commands run in the current shell session.
+    CurrentSession,
+    /// Commands that exited successfully.
+    Successful,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaseMode {
+    /// Case-sensitive only when the search contains an uppercase letter.
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailureFilter {
+    Show,
+    /// Hide commands whose every recorded run failed.
+    Hide,
+    /// Show only commands whose every recorded run failed.
+    Only,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactMode {
+    /// Record commands with their secrets replaced by `<REDACTED>`.
+    Mask,
+    /// Don't record commands that contain secrets.
+    Refuse,
+    Off,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectionJoin {
+    /// Join marked commands with ` && `.
+    And,
+    /// Join marked commands with `; `.
+    Semicolon,
+    /// Put each marked command on its own line.
+    Newline,
+}
+
+impl SelectionJoin {
+    pub fn separator(&self) -> &'static str {
+        match self {
+            SelectionJoin::And => " && ",
+            SelectionJoin::Semicolon => "; ",
+            SelectionJoin::Newline => "\n",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -63,14 +315,14 @@ pub enum HistoryFormat {
     /// HISTTIMEFORMAT is assumed to be empty.
     Bash,
 
-    /// zsh format - commands in plain text, with multiline commands on multiple lines.
-    /// `McFly` does not currently handle joining these lines; they're treated as separate commands.
+    /// zsh format - commands in plain text, with multiline commands on multiple lines, each but
+    /// the last ending in a backslash. These lines are joined back into a single command.
     /// If --zsh-extended-history was given, `extended_history` will be true, and we'll strip the
     /// timestamp from the beginning of each command.
     Zsh { extended_history: bool },
 
     /// fish's pseudo-yaml, with commands stored as 'cmd' with multiple lines joined into one with
-    /// '\n', and with timestamps stored as 'when'.  ('paths' is ignored.)
+    /// an escaped '\n', and with timestamps stored as 'when'.  ('paths' is ignored.)
     /// (Some discussion of changing format: https://github.com/fish-shell/fish-shell/pull/6493)
     Fish,
 }
@@ -86,16 +338,151 @@ pub struct TimeRange {
     pub before: Option<i64>,
 }
 
+/// Which runs `prune` deletes: those selected by any of the criteria, except that only failed
+/// runs are deleted when `failed_only` is set, the `keep_last` most recent runs are kept, and
+/// runs of pinned commands are never deleted. On their own, `failed_only` and `keep_last` select
+/// every other run.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Delete runs of commands that match the ignore rules.
+    pub apply_ignore_rules: bool,
+    /// Delete runs older than this many seconds.
+    pub older_than: Option<i64>,
+    /// Never delete this many of the most recent runs.
+    pub keep_last: Option<u64>,
+    /// Delete runs that repeat the previous run in the same session.
+    pub dedupe_consecutive: bool,
+    pub failed_only: bool,
+}
+
+impl RetentionPolicy {
+    /// Whether the policy deletes nothing.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        !self.apply_ignore_rules
+            && self.older_than.is_none()
+            && self.keep_last.is_none()
+            && !self.dedupe_consecutive
+            && !self.failed_only
+    }
+}
+
+/// A setting that can be given in `config.toml`, and overridden by an environment variable.
 #[derive(Debug)]
+pub struct SettingKey {
+    pub key: &'static str,
+    pub env: &'static str,
+    /// The default value, as it would be written in `config.toml`.
+    pub default: Option<&'static str>,
+}
+
+const fn setting(key: &'static str, env: &'static str, default: &'static str) -> SettingKey {
+    SettingKey {
+        key,
+        env,
+        default: Some(default),
+    }
+}
+
+/// Every setting that `Settings::set` understands. Each can be given in `config.toml` or by its
+/// environment variable, with the command line taking precedence over the environment, the
+/// environment over config files, and config files over the defaults.
+pub const SETTINGS: [SettingKey; 32] = [
+    setting("results", "MCFLY_RESULTS", "30"),
+    setting("fuzzy", "MCFLY_FUZZY", "0"),
+    setting("case_mode", "MCFLY_CASE_MODE", "smart"),
+    setting("results_sort", "MCFLY_RESULTS_SORT", "rank"),
+    setting("results_filter", "MCFLY_RESULTS_FILTER", "global"),
+    setting("failures", "MCFLY_FAILURES", "show"),
+    setting("selection_join", "MCFLY_SELECTION_JOIN", "and"),
+    setting("interface_view", "MCFLY_INTERFACE_VIEW", "top"),
+    setting("height", "MCFLY_HEIGHT", "full"),
+    setting("columns", "MCFLY_COLUMNS", "relative-time"),
+    setting("preview", "MCFLY_PREVIEW", "false"),
+    setting("preview_position", "MCFLY_PREVIEW_POSITION", "side"),
+    setting("syntax_highlight", "MCFLY_SYNTAX_HIGHLIGHT", "false"),
+    setting("mouse", "MCFLY_MOUSE", "false"),
+    setting("light", "MCFLY_LIGHT", "false"),
+    setting("theme", "MCFLY_THEME", "default"),
+    setting("key_scheme", "MCFLY_KEY_SCHEME", "emacs"),
+    setting("prompt", "MCFLY_PROMPT", "$"),
+    setting("disable_menu", "MCFLY_DISABLE_MENU", "false"),
+    setting("group_results", "MCFLY_GROUP_RESULTS", "false"),
+    setting("disable_run_command", "MCFLY_DISABLE_RUN_COMMAND", "false"),
+    setting(
+        "delete_without_confirm",
+        "MCFLY_DELETE_WITHOUT_CONFIRM",
+        "false",
+    ),
+    SettingKey {
+        key: "history_limit",
+        env: "MCFLY_HISTORY_LIMIT",
+        default: None,
+    },
+    setting("trash_days", "MCFLY_TRASH_DAYS", "30"),
+    SettingKey {
+        key: "retention_older_than",
+        env: "MCFLY_RETENTION_OLDER_THAN",
+        default: None,
+    },
+    SettingKey {
+        key: "retention_keep_last",
+        env: "MCFLY_RETENTION_KEEP_LAST",
+        default: None,
+    },
+    setting(
+        "retention_dedupe_consecutive",
+        "MCFLY_RETENTION_DEDUPE_CONSECUTIVE",
+        "false",
+    ),
+    setting("record", "MCFLY_RECORD", "true"),
+    setting("ignore_defaults", "MCFLY_IGNORE_DEFAULTS", "true"),
+    setting("redact", "MCFLY_REDACT", "mask"),
+    setting("redact_high_entropy", "MCFLY_REDACT_HIGH_ENTROPY", "false"),
+    setting("debug", "MCFLY_DEBUG", "false"),
+];
+
+/// The name of the per-project config file, found in the current directory or above it.
+pub const PROJECT_CONFIG_FILE: &str = ".mcfly.toml";
+
+/// The only keys a project's `.mcfly.toml` can set. Anything else, such as retention or key
+/// bindings, is left to the user, since a cloned repository shouldn't be able to change them.
+pub const PROJECT_CONFIG_KEYS: [&str; 4] = ["results_filter", "fuzzy", "ignore", "record"];
+
+/// Where the effective value of a setting came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingSource {
+    Default,
+    UserConfig,
+    ProjectConfig,
+    Env,
+    Cli,
+}
+
+impl SettingSource {
+    #[must_use]
+    pub fn label(self, setting: &SettingKey) -> &'static str {
+        match self {
+            SettingSource::Default => "default",
+            SettingSource::UserConfig => "config.toml",
+            SettingSource::ProjectConfig => PROJECT_CONFIG_FILE,
+            SettingSource::Env => setting.env,
+            SettingSource::Cli => "command line",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Colors {
     pub menubar_bg: Color,
     pub menubar_fg: Color,
-    pub darkmode_colors: DarkModeColors,
-    pub lightmode_colors: LightModeColors,
+    pub darkmode_colors: Palette,
+    pub lightmode_colors: Palette,
 }
 
-#[derive(Debug)]
-pub struct DarkModeColors {
+/// The colors used in one of dark or light mode.
+#[derive(Debug, Clone)]
+pub struct Palette {
     pub prompt: Color,
     pub timing: Color,
     pub results_fg: Color,
@@ -104,18 +491,129 @@ pub struct DarkModeColors {
     pub results_selection_fg: Color,
     pub results_selection_bg: Color,
     pub results_selection_hl: Color,
+    pub syntax_command: Color,
+    pub syntax_flag: Color,
+    pub syntax_string: Color,
+    pub syntax_variable: Color,
+    pub syntax_operator: Color,
 }
 
-#[derive(Debug)]
-pub struct LightModeColors {
-    pub prompt: Color,
-    pub timing: Color,
-    pub results_fg: Color,
-    pub results_bg: Color,
-    pub results_hl: Color,
-    pub results_selection_fg: Color,
-    pub results_selection_bg: Color,
-    pub results_selection_hl: Color,
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            menubar_bg: Color::Blue,
+            menubar_fg: Color::White,
+            darkmode_colors: Palette {
+                prompt: Color::White,
+                timing: Color::Blue,
+                results_fg: Color::White,
+                results_bg: Color::Black,
+                results_hl: Color::Blue,
+                results_selection_fg: Color::Black,
+                results_selection_bg: Color::DarkGrey,
+                results_selection_hl: Color::DarkGreen,
+                syntax_command: Color::Green,
+                syntax_flag: Color::Cyan,
+                syntax_string: Color::Yellow,
+                syntax_variable: Color::Magenta,
+                syntax_operator: Color::Red,
+            },
+            lightmode_colors: Palette {
+                prompt: Color::Black,
+                timing: Color::DarkBlue,
+                results_fg: Color::Black,
+                results_bg: Color::White,
+                results_hl: Color::Blue,
+                results_selection_fg: Color::White,
+                results_selection_bg: Color::DarkGrey,
+                results_selection_hl: Color::Grey,
+                syntax_command: Color::DarkGreen,
+                syntax_flag: Color::DarkCyan,
+                syntax_string: Color::DarkYellow,
+                syntax_variable: Color::DarkMagenta,
+                syntax_operator: Color::DarkRed,
+            },
+        }
+    }
+}
+
+impl Colors {
+    #[must_use]
+    pub fn palette(&self, lightmode: bool) -> &Palette {
+        if lightmode {
+            &self.lightmode_colors
+        } else {
+            &self.darkmode_colors
+        }
+    }
+
+    /// Override colors from a `[colors]` table, which has `menubar`, `darkmode` and `lightmode`
+    /// sections. Theme files use the same layout.
+    pub fn merge_config(&mut self, config: config::Map<String, Value>) {
+        for (section, table) in config {
+            let Ok(table) = table.into_table() else {
+                continue;
+            };
+            for (key, value) in table {
+                if let Some(color) = value
+                    .into_string()
+                    .ok()
+                    .and_then(|v| theme::parse_color(&v))
+                    && let Some(slot) = self.color_mut(&section, &key)
+                {
+                    *slot = color;
+                }
+            }
+        }
+    }
+
+    fn color_mut(&mut self, section: &str, key: &str) -> Option<&mut Color> {
+        match (section, key) {
+            ("menubar", "bg") => Some(&mut self.menubar_bg),
+            ("menubar", "fg") => Some(&mut self.menubar_fg),
+            ("darkmode", key) => self.darkmode_colors.color_mut(key),
+            ("lightmode", key) => self.lightmode_colors.color_mut(key),
+            _ => None,
+        }
+    }
+}
+
+impl Palette {
+    /// The terminal's default colors, for when `NO_COLOR` is set.
+    pub const NO_COLOR: Palette = Palette {
+        prompt: Color::Reset,
+        timing: Color::Reset,
+        results_fg: Color::Reset,
+        results_bg: Color::Reset,
+        results_hl: Color::Reset,
+        results_selection_fg: Color::Reset,
+        results_selection_bg: Color::Reset,
+        results_selection_hl: Color::Reset,
+        syntax_command: Color::Reset,
+        syntax_flag: Color::Reset,
+        syntax_string: Color::Reset,
+        syntax_variable: Color::Reset,
+        syntax_operator: Color::Reset,
+    };
+
+    fn color_mut(&mut self, key: &str) -> Option<&mut Color> {
+        match key {
+            "prompt" => Some(&mut self.prompt),
+            "timing" => Some(&mut self.timing),
+            "results_fg" => Some(&mut self.results_fg),
+            "results_bg" => Some(&mut self.results_bg),
+            "results_hl" => Some(&mut self.results_hl),
+            "results_selection_fg" => Some(&mut self.results_selection_fg),
+            "results_selection_bg" => Some(&mut self.results_selection_bg),
+            "results_selection_hl" => Some(&mut self.results_selection_hl),
+            "syntax_command" => Some(&mut self.syntax_command),
+            "syntax_flag" => Some(&mut self.syntax_flag),
+            "syntax_string" => Some(&mut self.syntax_string),
+            "syntax_variable" => Some(&mut self.syntax_variable),
+            "syntax_operator" => Some(&mut self.syntax_operator),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -123,6 +621,7 @@ pub struct Settings {
     pub mode: Mode,
     pub debug: bool,
     pub fuzzy: i16,
+    pub case_mode: CaseMode,
     pub session_id: String,
     pub mcfly_history: PathBuf,
     pub output_selection: Option<String>,
@@ -135,15 +634,34 @@ pub struct Settings {
     pub append_to_histfile: Option<String>,
     pub refresh_training_cache: bool,
     pub lightmode: bool,
+    /// Whether to detect `lightmode` from the terminal's background color.
+    pub auto_lightmode: bool,
+    /// Whether `NO_COLOR` is set, in which case the interface uses only text attributes.
+    pub no_color: bool,
     pub key_scheme: KeyScheme,
     pub history_format: HistoryFormat,
     pub limit: Option<i64>,
+    /// Days deleted commands stay in the trash before they are purged.
+    pub trash_days: u16,
+    pub trash_mode: TrashMode,
+    /// Whether `trash restore` should restore everything.
+    pub restore_all: bool,
     pub skip_environment_check: bool,
     pub init_mode: InitMode,
     pub delete_without_confirm: bool,
     pub interface_view: InterfaceView,
+    pub interface_height: InterfaceHeight,
+    pub columns: Vec<ResultColumn>,
+    pub preview: bool,
+    pub syntax_highlight: bool,
+    pub preview_position: PreviewPosition,
     pub result_sort: ResultSort,
     pub result_filter: ResultFilter,
+    pub failure_filter: FailureFilter,
+    pub group_results: bool,
+    pub selection_join: SelectionJoin,
+    pub key_bindings: KeyBindings,
+    pub mouse: bool,
     pub disable_menu: bool,
     pub prompt: String,
     pub disable_run_command: bool,
@@ -152,11 +670,42 @@ pub struct Settings {
     pub pattern: Option<Regex>,
     pub dump_format: DumpFormat,
     pub colors: Colors,
+    pub theme: String,
+    /// The `.mcfly.toml` that applies to the current directory, if any.
+    pub project_config_path: Option<PathBuf>,
+    /// Whether `add` records commands.
+    pub record: bool,
+    /// Rules for commands that are never recorded.
+    pub ignore_rules: Vec<IgnoreRule>,
+    /// Whether the built-in ignore rules apply.
+    pub ignore_defaults: bool,
+    /// What `add` does with commands that contain secrets.
+    pub redact: RedactMode,
+    pub redactor: Redactor,
+    /// Whether `scan` should redact the secrets it finds.
+    pub scan_redact: bool,
+    /// Whether `add` should only report what it would do.
+    pub dry_run: bool,
+    /// What `prune` deletes.
+    pub prune_policy: RetentionPolicy,
+    /// What is pruned automatically, at most once a day.
+    pub retention: RetentionPolicy,
+    /// The values applied to each of `SETTINGS` that isn't at its default, and their sources.
+    pub setting_values: HashMap<&'static str, (String, SettingSource)>,
+    pub config_mode: ConfigMode,
+    /// The setting named by `config get` or `config set`.
+    pub config_key: Option<String>,
+    /// The value given to `config set`.
+    pub config_value: Option<String>,
     pub stats_min_cmd_length: i16,
     pub stats_cmds: i16,
     pub stats_dirs: i16,
     pub stats_global_commands_to_ignore: i16,
     pub stats_only_dir: Option<String>,
+    /// The directory a `pin` or `unpin` is limited to, if any.
+    pub pin_dir: Option<String>,
+    /// The note given to `annotate`, if any.
+    pub note: Option<String>,
 }
 
 impl Default for Settings {
@@ -176,16 +725,32 @@ impl Default for Settings {
             append_to_histfile: None,
             debug: false,
             fuzzy: 0,
+            case_mode: CaseMode::Smart,
             lightmode: false,
+            auto_lightmode: false,
+            no_color: false,
             key_scheme: KeyScheme::Emacs,
             history_format: HistoryFormat::Bash,
             limit: None,
+            trash_days: 30,
+            trash_mode: TrashMode::List,
+            restore_all: false,
             skip_environment_check: false,
             init_mode: InitMode::Bash,
             delete_without_confirm: false,
             interface_view: InterfaceView::Top,
+            interface_height: InterfaceHeight::Full,
+            columns: vec![ResultColumn::new(ColumnKind::RelativeTime)],
+            preview: false,
+            syntax_highlight: false,
+            preview_position: PreviewPosition::Side,
             result_sort: ResultSort::Rank,
             result_filter: ResultFilter::Global,
+            failure_filter: FailureFilter::Show,
+            group_results: false,
+            selection_join: SelectionJoin::And,
+            key_bindings: KeyBindings::default(),
+            mouse: false,
             disable_menu: false,
             prompt: String::from("$"),
             disable_run_command: false,
@@ -193,35 +758,29 @@ impl Default for Settings {
             sort_order: SortOrder::default(),
             pattern: None,
             dump_format: DumpFormat::default(),
-            colors: Colors {
-                menubar_bg: Color::Blue,
-                menubar_fg: Color::White,
-                darkmode_colors: DarkModeColors {
-                    prompt: Color::White,
-                    timing: Color::Blue,
-                    results_fg: Color::White,
-                    results_bg: Color::Black,
-                    results_hl: Color::Blue,
-                    results_selection_fg: Color::Black,
-                    results_selection_bg: Color::DarkGrey,
-                    results_selection_hl: Color::DarkGreen,
-                },
-                lightmode_colors: LightModeColors {
-                    prompt: Color::Black,
-                    timing: Color::DarkBlue,
-                    results_fg: Color::Black,
-                    results_bg: Color::White,
-                    results_hl: Color::Blue,
-                    results_selection_fg: Color::White,
-                    results_selection_bg: Color::DarkGrey,
-                    results_selection_hl: Color::Grey,
-                },
-            },
+            colors: Colors::default(),
+            theme: String::from("default"),
+            project_config_path: None,
+            record: true,
+            ignore_rules: Vec::new(),
+            ignore_defaults: true,
+            redact: RedactMode::Mask,
+            redactor: Redactor::default(),
+            scan_redact: false,
+            dry_run: false,
+            prune_policy: RetentionPolicy::default(),
+            retention: RetentionPolicy::default(),
+            setting_values: HashMap::new(),
+            config_mode: ConfigMode::Show,
+            config_key: None,
+            config_value: None,
             stats_min_cmd_length: 0,
             stats_cmds: 10,
             stats_dirs: 0,
             stats_global_commands_to_ignore: 10,
             stats_only_dir: None,
+            pin_dir: None,
+            note: None,
         }
     }
 }
@@ -231,41 +790,33 @@ impl Settings {
         let cli = Cli::parse();
 
         let mut settings = Settings {
-            skip_environment_check: cli.is_init(),
+            skip_environment_check: cli.is_init() || cli.is_config(),
             ..Default::default()
         };
 
-        settings.debug = cli.debug || is_env_var_truthy("MCFLY_DEBUG");
-        settings.limit = env::var("MCFLY_HISTORY_LIMIT")
-            .ok()
-            .and_then(|o| o.parse::<i64>().ok());
-
-        settings.interface_view = match env::var("MCFLY_INTERFACE_VIEW") {
-            Ok(val) => match val.to_uppercase().as_str() {
-                "TOP" => InterfaceView::Top,
-                "BOTTOM" => InterfaceView::Bottom,
-                _ => InterfaceView::Top,
-            },
-            _ => InterfaceView::Top,
-        };
+        let user_config = Settings::read_config(&Settings::mcfly_config_path());
+        settings.project_config_path = cli
+            .directory()
+            .map(PathBuf::from)
+            .or_else(|| env::var("PWD").ok().map(PathBuf::from))
+            .or_else(|| env::current_dir().ok())
+            .and_then(|dir| Settings::find_project_config(&dir));
+        let project_config = settings.project_config_path.as_deref().and_then(|path| {
+            Settings::read_config(path).map(|config| Settings::project_settings(config, path))
+        });
+        settings.merge_sources(user_config.as_ref(), project_config.as_ref(), |name| {
+            env::var(name).ok()
+        });
 
-        settings.result_sort = match env::var("MCFLY_RESULTS_SORT") {
-            Ok(val) => match val.to_uppercase().as_str() {
-                "RANK" => ResultSort::Rank,
-                "LAST_RUN" => ResultSort::LastRun,
-                _ => ResultSort::Rank,
-            },
-            _ => ResultSort::Rank,
-        };
+        if settings.ignore_defaults {
+            settings
+                .ignore_rules
+                .splice(0..0, ignore_rules::default_rules());
+        }
 
-        settings.result_filter = match env::var("MCFLY_RESULTS_FILTER") {
-            Ok(val) => match val.to_uppercase().as_str() {
-                "GLOBAL" => ResultFilter::Global,
-                "CURRENT_DIRECTORY" => ResultFilter::CurrentDirectory,
-                _ => ResultFilter::Global,
-            },
-            _ => ResultFilter::Global,
-        };
+        if cli.debug {
+            settings.set_from("debug", "true", SettingSource::Cli);
+        }
 
         settings.session_id = cli.session_id.unwrap_or_else(||
             env::var("MCFLY_SESSION_ID")
@@ -316,8 +867,10 @@ impl Settings {
                 when,
                 directory,
                 old_directory,
+                dry_run,
             } => {
                 settings.mode = Mode::Add;
+                settings.dry_run = dry_run;
 
                 settings.when_run = when.or_else(|| {
                     Some(
@@ -369,30 +922,17 @@ impl Settings {
 
                 settings.dir = directory.unwrap_or_else(pwd);
 
-                if let Ok(results) = env::var("MCFLY_RESULTS")
-                    && let Ok(results) = u16::from_str(&results)
-                {
-                    settings.results = results;
-                }
-
                 if let Some(results) = results {
-                    settings.results = results;
-                }
-
-                if let Ok(fuzzy) = env::var("MCFLY_FUZZY") {
-                    if let Ok(fuzzy) = i16::from_str(&fuzzy) {
-                        settings.fuzzy = fuzzy;
-                    } else if fuzzy.to_lowercase() != "false" {
-                        settings.fuzzy = 2;
-                    }
+                    settings.set_from("results", &results.to_string(), SettingSource::Cli);
                 }
 
                 if let Some(fuzzy) = fuzzy {
-                    settings.fuzzy = fuzzy;
+                    settings.set_from("fuzzy", &fuzzy.to_string(), SettingSource::Cli);
                 }
 
-                settings.delete_without_confirm =
-                    delete_without_confirm || is_env_var_truthy("MCFLY_DELETE_WITHOUT_CONFIRM");
+                if delete_without_confirm {
+                    settings.set_from("delete_without_confirm", "true", SettingSource::Cli);
+                }
 
                 settings.output_selection = output_selection;
 
@@ -431,6 +971,78 @@ impl Settings {
                 settings.dir = new_dir_path;
             }
 
+            SubCommand::Pin { command, directory } => {
+                settings.mode = Mode::Pin;
+                settings.command = command.join(" ");
+                settings.pin_dir = directory.map(|dir| normalize_path(&dir));
+            }
+
+            SubCommand::Unpin { command, directory } => {
+                settings.mode = Mode::Unpin;
+                settings.command = command.join(" ");
+                settings.pin_dir = directory.map(|dir| normalize_path(&dir));
+            }
+
+            SubCommand::Annotate { command, note } => {
+                settings.mode = Mode::Annotate;
+                settings.command = command.join(" ");
+                settings.note = note;
+            }
+
+            SubCommand::Trash { action } => {
+                settings.mode = Mode::Trash;
+                settings.trash_mode = match action {
+                    TrashAction::List => TrashMode::List,
+                    TrashAction::Restore { command, all } => {
+                        settings.command = command.join(" ");
+                        settings.restore_all = all;
+                        TrashMode::Restore
+                    }
+                    TrashAction::Empty => TrashMode::Empty,
+                };
+            }
+
+            SubCommand::Prune {
+                apply_ignore_rules,
+                older_than,
+                keep_last,
+                dedupe_consecutive,
+                failed_only,
+                dry_run,
+            } => {
+                settings.mode = Mode::Prune;
+                settings.dry_run = dry_run;
+                settings.prune_policy = RetentionPolicy {
+                    apply_ignore_rules,
+                    older_than,
+                    keep_last,
+                    dedupe_consecutive,
+                    failed_only,
+                };
+            }
+
+            SubCommand::Scan { redact } => {
+                settings.mode = Mode::Scan;
+                settings.scan_redact = redact;
+            }
+
+            SubCommand::Config { action } => {
+                settings.mode = Mode::Config;
+                settings.config_mode = match action {
+                    ConfigAction::Show => ConfigMode::Show,
+                    ConfigAction::Get { key } => {
+                        settings.config_key = Some(key);
+                        ConfigMode::Get
+                    }
+                    ConfigAction::Set { key, value } => {
+                        settings.config_key = Some(key);
+                        settings.config_value = Some(value);
+                        ConfigMode::Set
+                    }
+                    ConfigAction::Path => ConfigMode::Path,
+                };
+            }
+
             SubCommand::Init { shell } => {
                 settings.mode = Mode::Init;
 
@@ -475,185 +1087,319 @@ impl Settings {
             }
         }
 
-        settings.lightmode = is_env_var_truthy("MCFLY_LIGHT");
+        // See https://no-color.org.
+        settings.no_color = env::var("NO_COLOR").is_ok_and(|v| !v.is_empty());
 
-        settings.disable_menu = is_env_var_truthy("MCFLY_DISABLE_MENU");
+        settings.apply_colors(user_config.iter());
 
-        settings.disable_run_command = is_env_var_truthy("MCFLY_DISABLE_RUN_COMMAND");
-
-        settings.key_scheme = match env::var("MCFLY_KEY_SCHEME").as_ref().map(String::as_ref) {
-            Ok("vim") => KeyScheme::Vim,
-            _ => KeyScheme::Emacs,
-        };
+        settings
+    }
 
-        if let Ok(prompt) = env::var("MCFLY_PROMPT")
-            && prompt.chars().count() == 1
-        {
-            settings.prompt = prompt;
+    /// Read a `config.toml`, if it exists. A file that can't be parsed is skipped with a warning.
+    #[must_use]
+    pub fn read_config(path: &Path) -> Option<HashMap<String, Value>> {
+        if !path.exists() {
+            return None;
         }
+        config::File::from(path.to_path_buf())
+            .collect()
+            .map_err(|err| {
+                eprintln!(
+                    "McFly: Skipping config file {}, which couldn't be read ({err})",
+                    path.display()
+                );
+            })
+            .ok()
+    }
 
-        settings
+    /// The settings in a project's config that are in `PROJECT_CONFIG_KEYS`, warning about the
+    /// rest.
+    fn project_settings(config_map: HashMap<String, Value>, path: &Path) -> HashMap<String, Value> {
+        config_map
+            .into_iter()
+            .filter(|(key, _)| {
+                let allowed = PROJECT_CONFIG_KEYS.contains(&key.as_str());
+                if !allowed {
+                    eprintln!(
+                        "McFly: Ignoring '{key}' in {}, only {} can be set there",
+                        path.display(),
+                        PROJECT_CONFIG_KEYS.join(", ")
+                    );
+                }
+                allowed
+            })
+            .collect()
     }
 
-    pub fn load_config(&mut self) {
-        let config_path = Settings::mcfly_config_path();
-        if config_path.exists() {
-            let config = config::File::from(config_path);
-            if let Ok(config_map) = config.collect() {
-                self.merge_config(config_map);
-            }
-        };
+    /// The nearest `.mcfly.toml` in `dir` or any directory above it.
+    fn find_project_config(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+            .find(|path| path.is_file())
     }
 
-    pub fn merge_config(&mut self, config_map: HashMap<String, Value>) {
-        let color_config = config_map.get("colors");
+    /// Apply the settings in a config file. Only the settings in `SETTINGS`, `columns`, `ignore`,
+    /// `redact_patterns` and `keys` are handled here; colors are applied by `apply_colors` once the theme is known.
+    pub fn merge_config(&mut self, config_map: &HashMap<String, Value>, source: SettingSource) {
+        for setting in &SETTINGS {
+            let Some(value) = config_map.get(setting.key) else {
+                continue;
+            };
 
-        let menubar_config = color_config
-            .and_then(|v| v.clone().into_table().ok())
-            .and_then(|v| v.get("menubar").and_then(|v| v.clone().into_table().ok()));
+            // Columns can also be a list of names, or of tables with a name, width and color.
+            if let Ok(columns) = value.clone().into_array() {
+                if setting.key == "columns" {
+                    self.columns = columns
+                        .into_iter()
+                        .filter_map(ResultColumn::from_config)
+                        .collect();
+                    let names = self.columns.iter().map(|c| c.kind.name()).join(",");
+                    self.setting_values.insert(setting.key, (names, source));
+                }
+                continue;
+            }
 
-        if let Some(menubar_config) = menubar_config {
-            if let Some(menubar_bg) = menubar_config
-                .get("bg")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.menubar_bg = menubar_bg;
+            if let Ok(value) = value.clone().into_string() {
+                self.set_from(setting.key, &value, source);
             }
-            if let Some(menubar_fg) = menubar_config
-                .get("fg")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.menubar_fg = menubar_fg;
+        }
+
+        // Ignore rules add up, so that a project can ignore more than the user does.
+        if let Some(ignore) = config_map
+            .get("ignore")
+            .and_then(|v| v.clone().into_array().ok())
+        {
+            for rule in ignore {
+                match IgnoreRule::from_config(rule) {
+                    Ok(rule) => self.ignore_rules.push(rule),
+                    Err(err) => eprintln!("McFly: Invalid ignore rule, {err}"),
+                }
             }
         }
 
-        let darkmode_config = color_config
-            .and_then(|v| v.clone().into_table().ok())
-            .and_then(|v| v.get("darkmode").and_then(|v| v.clone().into_table().ok()));
+        if let Some(patterns) = config_map
+            .get("redact_patterns")
+            .and_then(|v| v.clone().into_array().ok())
+        {
+            for pattern in patterns {
+                let added = pattern
+                    .into_string()
+                    .map_err(|_| String::from("expected a string"))
+                    .and_then(|pattern| self.redactor.add_pattern(&pattern));
+                if let Err(err) = added {
+                    eprintln!("McFly: Invalid redact pattern, {err}");
+                }
+            }
+        }
 
-        if let Some(darkmode_config) = darkmode_config {
-            if let Some(prompt) = darkmode_config
-                .get("prompt")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.darkmode_colors.prompt = prompt;
+        if let Some(keys_config) = config_map
+            .get("keys")
+            .and_then(|v| v.clone().into_table().ok())
+        {
+            // Sort so that the menubar label is stable when one action is bound to several chords.
+            let mut keys_config: Vec<(String, Value)> = keys_config.into_iter().collect();
+            keys_config.sort_by(|a, b| b.0.cmp(&a.0));
+            for (chord, action) in keys_config {
+                if let (Some(chord), Some(action)) = (
+                    KeyChord::parse(&chord),
+                    action
+                        .into_string()
+                        .ok()
+                        .and_then(|v| Action::from_name(&v)),
+                ) {
+                    self.key_bindings.bind(chord, action);
+                }
             }
-            if let Some(timing) = darkmode_config
-                .get("timing")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.darkmode_colors.timing = timing;
+        }
+    }
+
+    /// Apply the user's `config.toml`, then the project's `.mcfly.toml`, then the environment
+    /// variables looked up with `env`, each taking precedence over the one before. The command
+    /// line is applied on top of these.
+    fn merge_sources(
+        &mut self,
+        user_config: Option<&HashMap<String, Value>>,
+        project_config: Option<&HashMap<String, Value>>,
+        env: impl Fn(&str) -> Option<String>,
+    ) {
+        if let Some(user_config) = user_config {
+            self.merge_config(user_config, SettingSource::UserConfig);
+        }
+        if let Some(project_config) = project_config {
+            self.merge_config(project_config, SettingSource::ProjectConfig);
+        }
+        self.merge_env(env);
+    }
+
+    /// Apply the `MCFLY_*` environment variables, which take precedence over config files.
+    fn merge_env(&mut self, env: impl Fn(&str) -> Option<String>) {
+        for setting in &SETTINGS {
+            if let Some(value) = env(setting.env) {
+                self.set_from(setting.key, &value, SettingSource::Env);
             }
-            if let Some(results_fg) = darkmode_config
-                .get("results_fg")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
+        }
+    }
+
+    /// Use the colors of the configured theme, with the `[colors]` of each config file on top.
+    fn apply_colors<'c>(&mut self, configs: impl Iterator<Item = &'c HashMap<String, Value>>) {
+        self.colors = theme::load(&self.theme).unwrap_or_else(|| {
+            eprintln!(
+                "McFly: Unknown theme '{}', the built-in themes are {}",
+                self.theme,
+                theme::BUILTIN_THEMES.join(", ")
+            );
+            Colors::default()
+        });
+
+        for config_map in configs {
+            if let Some(colors_config) = config_map
+                .get("colors")
+                .and_then(|v| v.clone().into_table().ok())
             {
-                self.colors.darkmode_colors.results_fg = results_fg;
+                self.colors.merge_config(colors_config);
             }
-            if let Some(results_bg) = darkmode_config
-                .get("results_bg")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.darkmode_colors.results_bg = results_bg;
+        }
+    }
+
+    /// Apply `value` to the setting called `key`, remembering where it came from. Invalid
+    /// values are ignored, leaving the setting as it was.
+    fn set_from(&mut self, key: &str, value: &str, source: SettingSource) {
+        if self.set(key, value).is_ok()
+            && let Some(setting) = SETTINGS.iter().find(|setting| setting.key == key)
+        {
+            self.setting_values
+                .insert(setting.key, (value.to_string(), source));
+        }
+    }
+
+    /// Apply `value` to the setting called `key`, as given in `config.toml` or by its
+    /// environment variable.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let invalid = || format!("Invalid value '{value}' for {key}");
+        let upper = value.to_uppercase();
+
+        match key {
+            "results" => self.results = value.parse().map_err(|_| invalid())?,
+            "fuzzy" => {
+                self.fuzzy = match value.parse() {
+                    Ok(fuzzy) => fuzzy,
+                    Err(_) if value.eq_ignore_ascii_case("false") => 0,
+                    Err(_) => 2,
+                }
             }
-            if let Some(results_hl) = darkmode_config
-                .get("results_hl")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.darkmode_colors.results_hl = results_hl;
+            "case_mode" => self.case_mode = CaseMode::from_name(value).ok_or_else(invalid)?,
+            "results_sort" => {
+                self.result_sort = match upper.as_str() {
+                    "RANK" => ResultSort::Rank,
+                    "LAST_RUN" => ResultSort::LastRun,
+                    "FREQUENCY" => ResultSort::Frequency,
+                    "FRECENCY" => ResultSort::Frecency,
+                    "RECENT_HERE" => ResultSort::RecentHere,
+                    _ => return Err(invalid()),
+                }
             }
-            if let Some(results_selection_fg) = darkmode_config
-                .get("results_selection_fg")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.darkmode_colors.results_selection_fg = results_selection_fg;
+            "results_filter" => {
+                self.result_filter = match upper.as_str() {
+                    "GLOBAL" => ResultFilter::Global,
+                    "CURRENT_DIRECTORY" => ResultFilter::CurrentDirectory,
+                    "CURRENT_SESSION" => ResultFilter::CurrentSession,
+                    "SUCCESSFUL" => ResultFilter::Successful,
+                    _ => return Err(invalid()),
+                }
             }
-            if let Some(results_selection_bg) = darkmode_config
-                .get("results_selection_bg")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.darkmode_colors.results_selection_bg = results_selection_bg;
+            "failures" => {
+                self.failure_filter = match upper.as_str() {
+                    "SHOW" => FailureFilter::Show,
+                    "HIDE" => FailureFilter::Hide,
+                    "ONLY" => FailureFilter::Only,
+                    _ => return Err(invalid()),
+                }
             }
-            if let Some(results_selection_hl) = darkmode_config
-                .get("results_selection_hl")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.darkmode_colors.results_selection_hl = results_selection_hl;
+            "selection_join" => {
+                self.selection_join = match upper.as_str() {
+                    "AND" => SelectionJoin::And,
+                    "SEMICOLON" => SelectionJoin::Semicolon,
+                    "NEWLINE" => SelectionJoin::Newline,
+                    _ => return Err(invalid()),
+                }
             }
-        }
-
-        let lightmode_config = color_config
-            .and_then(|v| v.clone().into_table().ok())
-            .and_then(|v| v.get("lightmode").and_then(|v| v.clone().into_table().ok()));
-
-        if let Some(lightmode_config) = lightmode_config {
-            if let Some(prompt) = lightmode_config
-                .get("prompt")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.lightmode_colors.prompt = prompt;
+            "interface_view" => {
+                self.interface_view = match upper.as_str() {
+                    "TOP" => InterfaceView::Top,
+                    "BOTTOM" => InterfaceView::Bottom,
+                    _ => return Err(invalid()),
+                }
             }
-            if let Some(timing) = lightmode_config
-                .get("timing")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.lightmode_colors.timing = timing;
+            "height" => {
+                self.interface_height = InterfaceHeight::parse(value).ok_or_else(invalid)?
             }
-            if let Some(results_fg) = lightmode_config
-                .get("results_fg")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.lightmode_colors.results_fg = results_fg;
+            "columns" => self.columns = ResultColumn::parse_list(value),
+            "preview" => self.preview = is_truthy(value),
+            "preview_position" => {
+                self.preview_position = match upper.as_str() {
+                    "SIDE" => PreviewPosition::Side,
+                    "BOTTOM" => PreviewPosition::Bottom,
+                    _ => return Err(invalid()),
+                }
             }
-            if let Some(results_bg) = lightmode_config
-                .get("results_bg")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.lightmode_colors.results_bg = results_bg;
+            "syntax_highlight" => self.syntax_highlight = is_truthy(value),
+            "mouse" => self.mouse = is_truthy(value),
+            "light" => {
+                self.auto_lightmode = value.eq_ignore_ascii_case("auto");
+                self.lightmode = !self.auto_lightmode && is_truthy(value);
             }
-            if let Some(results_hl) = lightmode_config
-                .get("results_hl")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.lightmode_colors.results_hl = results_hl;
+            "theme" => value.clone_into(&mut self.theme),
+            "key_scheme" => {
+                self.key_scheme = match value {
+                    "vim" => KeyScheme::Vim,
+                    "emacs" => KeyScheme::Emacs,
+                    _ => return Err(invalid()),
+                }
             }
-            if let Some(results_selection_fg) = lightmode_config
-                .get("results_selection_fg")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.lightmode_colors.results_selection_fg = results_selection_fg;
+            "prompt" if value.chars().count() == 1 => value.clone_into(&mut self.prompt),
+            "prompt" => return Err(invalid()),
+            "disable_menu" => self.disable_menu = is_truthy(value),
+            "group_results" => self.group_results = is_truthy(value),
+            "disable_run_command" => self.disable_run_command = is_truthy(value),
+            "delete_without_confirm" => self.delete_without_confirm = is_truthy(value),
+            "history_limit" => self.limit = Some(value.parse().map_err(|_| invalid())?),
+            "trash_days" => self.trash_days = value.parse().map_err(|_| invalid())?,
+            "retention_older_than" => {
+                self.retention.older_than = Some(parse_duration(value).map_err(|_| invalid())?);
             }
-            if let Some(results_selection_bg) = lightmode_config
-                .get("results_selection_bg")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.lightmode_colors.results_selection_bg = results_selection_bg;
+            "retention_keep_last" => {
+                self.retention.keep_last = Some(value.parse().map_err(|_| invalid())?);
             }
-            if let Some(results_selection_hl) = lightmode_config
-                .get("results_selection_hl")
-                .and_then(|v| v.clone().into_string().ok())
-                .and_then(|v| Color::from_str(v.as_str()).ok())
-            {
-                self.colors.lightmode_colors.results_selection_hl = results_selection_hl;
+            "retention_dedupe_consecutive" => self.retention.dedupe_consecutive = is_truthy(value),
+            "record" => self.record = is_truthy(value),
+            "ignore_defaults" => self.ignore_defaults = is_truthy(value),
+            "redact" => {
+                self.redact = match upper.as_str() {
+                    "MASK" => RedactMode::Mask,
+                    "REFUSE" => RedactMode::Refuse,
+                    "OFF" => RedactMode::Off,
+                    _ => return Err(invalid()),
+                }
             }
+            "redact_high_entropy" => self.redactor.set_high_entropy(is_truthy(value)),
+            "debug" => self.debug = is_truthy(value),
+            _ => return Err(format!("Unknown setting '{key}'")),
         }
+
+        Ok(())
+    }
+
+    /// The effective value of the setting called `key`, and where it came from. `None` for a
+    /// setting without a default that isn't set.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<(String, SettingSource)> {
+        self.setting_values.get(key).cloned().or_else(|| {
+            SETTINGS
+                .iter()
+                .find(|setting| setting.key == key)
+                .and_then(|setting| setting.default)
+                .map(|default| (default.to_string(), SettingSource::Default))
+        })
     }
 
     // Use ~/.mcfly only if it already exists, otherwise create 'mcfly' folder in XDG_CACHE_DIR
@@ -664,6 +1410,15 @@ impl Settings {
         Settings::mcfly_base_path(cache_dir).join(PathBuf::from("training-cache.v1.csv"))
     }
 
+    /// The redactor that masks commands before they are stored, or `None` when redaction is off.
+    #[must_use]
+    pub fn active_redactor(&self) -> Option<&Redactor> {
+        match self.redact {
+            RedactMode::Off => None,
+            RedactMode::Mask | RedactMode::Refuse => Some(&self.redactor),
+        }
+    }
+
     // Use ~/.mcfly only if it already exists, otherwise create 'mcfly' folder in XDG_DATA_DIR
     #[must_use]
     pub fn mcfly_db_path() -> PathBuf {
@@ -676,12 +1431,58 @@ impl Settings {
         Settings::mcfly_base_path(data_local_dir).join(PathBuf::from("history.db"))
     }
 
-    // Use ~/.mcfly only if it already exists, otherwise create 'mcfly' folder in XDG_DATA_DIR
+    // Use ~/.mcfly only if it already exists, otherwise create 'mcfly' folder in XDG_CONFIG_DIR
     #[must_use]
     pub fn mcfly_config_path() -> PathBuf {
+        let config_dir = Settings::mcfly_xdg_dir().config_dir().to_path_buf();
+        let config_path = Settings::mcfly_base_path(config_dir).join(PathBuf::from("config.toml"));
+
+        // Older versions kept config.toml in XDG_DATA_DIR.
         let data_dir = Settings::mcfly_xdg_dir().data_dir().to_path_buf();
+        let legacy_path = Settings::mcfly_base_path(data_dir).join(PathBuf::from("config.toml"));
+        if !config_path.exists() && legacy_path.exists() {
+            return legacy_path;
+        }
 
-        Settings::mcfly_base_path(data_dir).join(PathBuf::from("config.toml"))
+        config_path
+    }
+
+    /// Set `key` to `value` in `config.toml`, creating it if needed, and return its path.
+    pub fn write_config_value(key: &str, value: &str) -> Result<PathBuf, String> {
+        Settings::default().set(key, value)?;
+
+        let config_path = Settings::mcfly_config_path();
+        let contents = if config_path.exists() {
+            fs::read_to_string(&config_path).map_err(|err| {
+                format!(
+                    "Unable to read config file {} ({err})",
+                    config_path.display()
+                )
+            })?
+        } else {
+            String::new()
+        };
+        let contents = set_toml_key(&contents, key, &toml_value(value))
+            .map_err(|err| format!("Unable to update {} ({err})", config_path.display()))?;
+
+        if let Some(dir) = config_path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("Unable to create directory {} ({err})", dir.display()))?;
+        }
+        fs::write(&config_path, contents).map_err(|err| {
+            format!(
+                "Unable to write config file {} ({err})",
+                config_path.display()
+            )
+        })?;
+
+        Ok(config_path)
+    }
+
+    /// Theme files live in a `themes` directory beside `config.toml`.
+    #[must_use]
+    pub fn mcfly_themes_dir() -> PathBuf {
+        Settings::mcfly_config_path().with_file_name("themes")
     }
 
     fn mcfly_xdg_dir() -> ProjectDirs {
@@ -722,17 +1523,51 @@ pub fn pwd() -> String {
         .to_string()
 }
 
-fn is_env_var_truthy(name: &str) -> bool {
-    match env::var(name) {
-        Ok(val) => {
-            val != "F"
-                && val != "f"
-                && val != "false"
-                && val != "False"
-                && val != "FALSE"
-                && val != "0"
+/// `value` as it would be written in TOML: booleans and integers as they are, and anything else
+/// as a string.
+#[must_use]
+pub fn toml_value(value: &str) -> String {
+    if value == "true" || value == "false" || value.parse::<i64>().is_ok() {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// Set the top-level `key` to the TOML `value` in the TOML `contents`, leaving everything else,
+/// comments included, as it is.
+fn set_toml_key(contents: &str, key: &str, value: &str) -> Result<String, String> {
+    let mut document = contents
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|err| format!("unable to parse the config file ({err})"))?;
+    let value = value
+        .parse::<toml_edit::Value>()
+        .map_err(|err| format!("invalid value {value} ({err})"))?;
+    // Keep the key's decor, so that comments beside it stay.
+    match document.get_mut(key).and_then(|item| item.as_value_mut()) {
+        Some(existing) => {
+            let decor = existing.decor().clone();
+            *existing = value;
+            *existing.decor_mut() = decor;
+        }
+        None => document[key] = toml_edit::value(value),
+    }
+    Ok(document.to_string())
+}
+
+fn is_truthy(val: &str) -> bool {
+    val != "F" && val != "f" && val != "false" && val != "False" && val != "FALSE" && val != "0"
+}
+
+impl CaseMode {
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<CaseMode> {
+        match name.to_lowercase().as_str() {
+            "smart" => Some(CaseMode::Smart),
+            "sensitive" => Some(CaseMode::Sensitive),
+            "insensitive" => Some(CaseMode::Insensitive),
+            _ => None,
         }
-        Err(_) => false,
     }
 }
 
@@ -744,3 +1579,165 @@ impl TimeRange {
         self.since.is_none() && self.before.is_none()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        PROJECT_CONFIG_FILE, ResultFilter, SETTINGS, SettingSource, Settings, set_toml_key,
+    };
+    use config::{FileFormat, Source, Value};
+    use crossterm::style::Color;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+
+    fn config_map(toml: &str) -> HashMap<String, Value> {
+        config::File::from_str(toml, FileFormat::Toml)
+            .collect()
+            .unwrap()
+    }
+
+    #[test]
+    fn setting_defaults_are_valid() {
+        for setting in &SETTINGS {
+            if let Some(default) = setting.default {
+                assert_eq!(Settings::default().set(setting.key, default), Ok(()));
+            }
+        }
+        assert!(Settings::default().set("results", "many").is_err());
+        assert!(Settings::default().set("no_such_setting", "1").is_err());
+    }
+
+    #[test]
+    fn settings_precedence() {
+        let user = config_map(
+            "results = 10\nfuzzy = 1\nresults_filter = \"successful\"\npreview = true\n",
+        );
+        let project = config_map("fuzzy = 2\nresults_filter = \"current_directory\"\n");
+        let env = HashMap::from([("MCFLY_RESULTS", "30"), ("MCFLY_FUZZY", "3")]);
+        let mut settings = Settings::default();
+        settings.merge_sources(Some(&user), Some(&project), |name| {
+            env.get(name).map(ToString::to_string)
+        });
+        settings.set_from("results", "40", SettingSource::Cli);
+
+        let get = |key: &str| settings.get(key).unwrap();
+        assert_eq!(settings.results, 40);
+        assert_eq!(get("results"), (String::from("40"), SettingSource::Cli));
+        assert_eq!(settings.fuzzy, 3);
+        assert_eq!(get("fuzzy"), (String::from("3"), SettingSource::Env));
+        assert_eq!(settings.result_filter, ResultFilter::CurrentDirectory);
+        assert_eq!(get("results_filter").1, SettingSource::ProjectConfig);
+        assert!(settings.preview);
+        assert_eq!(get("preview").1, SettingSource::UserConfig);
+        assert_eq!(
+            get("case_mode"),
+            (String::from("smart"), SettingSource::Default)
+        );
+    }
+
+    #[test]
+    fn find_and_read_project_config() {
+        let root = std::env::temp_dir().join(format!("mcfly-project-{}", std::process::id()));
+        let nested = root.join("src").join("bin");
+        fs::create_dir_all(&nested).unwrap();
+        let path = root.join(PROJECT_CONFIG_FILE);
+        fs::write(&path, "fuzzy = 2\n").unwrap();
+
+        assert_eq!(Settings::find_project_config(&nested), Some(path.clone()));
+        assert_eq!(Settings::find_project_config(&root), Some(path.clone()));
+        assert!(Settings::read_config(&path).is_some());
+
+        fs::write(&path, "fuzzy = [\n").unwrap();
+        assert_eq!(Settings::read_config(&path), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn project_config_is_limited_and_adds_ignore_rules() {
+        let user = config_map("results = 10\nfuzzy = 1\nignore = [\"make deploy\"]\n");
+        let project = Settings::project_settings(
+            config_map(
+                "results = 5\nfuzzy = 2\nignore = [\"./reset-db\"]\nretention_keep_last = 0\n\
+                 trash_days = 0\ntheme = \"solarized\"\nredact_patterns = [\"x\"]\n\
+                 [keys]\nenter = \"delete\"\n",
+            ),
+            Path::new(PROJECT_CONFIG_FILE),
+        );
+        let mut keys: Vec<&str> = project.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, vec!["fuzzy", "ignore"]);
+
+        let mut settings = Settings::default();
+        settings.merge_sources(Some(&user), Some(&project), |_| None);
+        assert_eq!(settings.results, 10);
+        assert_eq!(settings.fuzzy, 2);
+        assert_eq!(settings.retention.keep_last, None);
+        assert_eq!(
+            settings
+                .ignore_rules
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["'make deploy'", "'./reset-db'"]
+        );
+    }
+
+    #[test]
+    fn syntax_colors_are_configurable() {
+        let mut settings = Settings::default();
+        let config = config_map(
+            "[colors.darkmode]\nsyntax_flag = \"blue\"\n\
+             [colors.lightmode]\nsyntax_operator = \"#ff0000\"\nsyntax_nothing = \"red\"\n",
+        );
+        settings.apply_colors([&config].into_iter());
+        assert_eq!(settings.colors.palette(false).syntax_flag, Color::Blue);
+        assert_eq!(
+            settings.colors.palette(true).syntax_operator,
+            Color::Rgb { r: 255, g: 0, b: 0 }
+        );
+        assert_eq!(settings.colors.palette(false).syntax_command, Color::Green);
+    }
+
+    #[test]
+    fn set_toml_key_keeps_the_rest_of_the_file() {
+        let set = |contents: &str, key: &str, value: &str| set_toml_key(contents, key, value);
+        assert_eq!(set("", "results", "50").as_deref(), Ok("results = 50\n"));
+        assert_eq!(
+            set(
+                "# Mine\nresults = 30 # the default\nfuzzy = 2\n",
+                "results",
+                "50"
+            )
+            .as_deref(),
+            Ok("# Mine\nresults = 50 # the default\nfuzzy = 2\n")
+        );
+        assert_eq!(
+            set(
+                "fuzzy = 2\n\n# Colors\n[colors.darkmode]\nprompt = \"red\"\n",
+                "results",
+                "50"
+            )
+            .as_deref(),
+            Ok("fuzzy = 2\nresults = 50\n\n# Colors\n[colors.darkmode]\nprompt = \"red\"\n")
+        );
+        assert_eq!(
+            set("[keys]\nresults = \"accept\"\n", "results", "50").as_deref(),
+            Ok("results = 50\n[keys]\nresults = \"accept\"\n")
+        );
+
+        // Values spanning several lines are replaced whole.
+        let columns = "columns = [\n  \"time\",\n  { name = \"dir\", width = 20 },\n]\nfuzzy = 2\n";
+        assert_eq!(
+            set(columns, "columns", "\"exit\"").as_deref(),
+            Ok("columns = \"exit\"\nfuzzy = 2\n")
+        );
+        assert_eq!(
+            set(columns, "results", "50").as_deref(),
+            Ok(format!("{columns}results = 50\n").as_str())
+        );
+
+        assert!(set("results = [\n", "results", "50").is_err());
+    }
+}
@@ -37,6 +37,52 @@ fn read_and_unmetafy(path: &Path) -> String {
     String::from_utf8_lossy(&buffer).to_string()
 }
 
+/// Split zsh history into entries. zsh writes each newline inside a multi-line command as a
+/// backslash followed by a newline.
+fn zsh_entries(history_contents: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut pending = String::new();
+    for line in history_contents.split('\n') {
+        if let Some(line) = line.strip_suffix('\\') {
+            pending.push_str(line);
+            pending.push('\n');
+        } else {
+            pending.push_str(line);
+            entries.push(std::mem::take(&mut pending));
+        }
+    }
+    if !pending.is_empty() {
+        entries.push(pending);
+    }
+    entries
+}
+
+/// fish stores each command on one line, escaping newlines as `\n` and backslashes as `\\`.
+fn fish_unescape(command: &str) -> String {
+    let mut out = String::with_capacity(command.len());
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn fish_escape(command: &str) -> String {
+    command.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
 #[allow(clippy::if_same_then_else)]
 fn has_leading_timestamp(line: &str) -> bool {
     let mut matched_chars = 0;
@@ -99,13 +145,19 @@ impl fmt::Display for HistoryCommand {
         match self.format {
             HistoryFormat::Bash => write!(f, "{}", self.command),
             HistoryFormat::Zsh { extended_history } => {
+                let command = self.command.replace('\n', "\\\n");
                 if extended_history {
-                    write!(f, ": {}:0;{}", self.when, self.command)
+                    write!(f, ": {}:0;{}", self.when, command)
                 } else {
-                    write!(f, "{}", self.command)
+                    write!(f, "{command}")
                 }
             }
-            HistoryFormat::Fish => writeln!(f, "- cmd: {}\n  when: {}", self.command, self.when),
+            HistoryFormat::Fish => writeln!(
+                f,
+                "- cmd: {}\n  when: {}",
+                fish_escape(&self.command),
+                self.when
+            ),
         }
     }
 }
@@ -134,8 +186,8 @@ pub fn full_history(path: &Path, history_format: HistoryFormat) -> Vec<HistoryCo
                 .duration_since(UNIX_EPOCH)
                 .unwrap_or_else(|err| panic!("McFly error: Time went backwards ({err})"))
                 .as_secs() as i64;
-            history_contents
-                .split('\n')
+            zsh_entries(&history_contents)
+                .iter()
                 .filter(|line| !has_leading_timestamp(line) && !line.is_empty())
                 .map(|line| zsh_timestamp_and_duration_regex.replace(line, ""))
                 .map(|line| HistoryCommand::new(line, when, history_format))
@@ -143,7 +195,7 @@ pub fn full_history(path: &Path, history_format: HistoryFormat) -> Vec<HistoryCo
         }
         HistoryFormat::Fish => {
             // Fish history format is not technically YAML.  This is a naive parser of the format,
-            // only caring about command strings (which are always on one line, with embedded
+            // only caring about command strings (which are always on one line, with escaped
             // newlines) and timestamps, ignoring the 'paths' field.
             let mut commands = Vec::new();
 
@@ -153,7 +205,7 @@ pub fn full_history(path: &Path, history_format: HistoryFormat) -> Vec<HistoryCo
             let mut command = None;
             for line in history_contents.split('\n') {
                 if line.starts_with("- cmd: ") {
-                    command = Some(line.split_at(7).1);
+                    command = Some(fish_unescape(line.split_at(7).1));
                 } else if line.starts_with("  when: ") {
                     let when_str = line.split_at(8).1;
                     let when =
@@ -260,7 +312,22 @@ pub fn append_history_entry(command: &HistoryCommand, path: &Path, debug: bool)
 
 #[cfg(test)]
 mod tests {
-    use super::has_leading_timestamp;
+    use super::{fish_escape, fish_unescape, has_leading_timestamp, zsh_entries};
+
+    #[test]
+    fn zsh_entries_joins_multi_line_commands() {
+        assert_eq!(
+            zsh_entries(": 1:0;for i in 1 2\\\ndo echo $i\\\ndone\n: 2:0;ls\n"),
+            vec![": 1:0;for i in 1 2\ndo echo $i\ndone", ": 2:0;ls", ""]
+        );
+    }
+
+    #[test]
+    fn fish_escaping_round_trips() {
+        assert_eq!(fish_unescape(r"echo a\nb\\n"), "echo a\nb\\n");
+        assert_eq!(fish_unescape(r"grep \d"), r"grep \d");
+        assert_eq!(fish_escape("echo a\nb\\n"), r"echo a\nb\\n");
+    }
 
     #[test]
     fn has_leading_timestamp_works() {
@@ -0,0 +1,262 @@
+/// The kinds of shell syntax that results are highlighted with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Plain,
+    /// The command word of each pipeline stage.
+    Command,
+    Flag,
+    String,
+    Variable,
+    /// Pipes, command separators, subshells and redirections.
+    Operator,
+}
+
+/// Classify each char of `command`. This is a lightweight approximation for display: it knows
+/// about quoting, variables, operators and redirections, but does not parse the shell grammar.
+#[must_use]
+pub fn tokenize(command: &str) -> Vec<TokenKind> {
+    let chars: Vec<char> = command.chars().collect();
+    let mut kinds = vec![TokenKind::Plain; chars.len()];
+    let mut command_position = true;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            ' ' | '\t' => i += 1,
+            '$' if next == Some('(') => {
+                kinds[i..i + 2].fill(TokenKind::Operator);
+                i += 2;
+                command_position = true;
+            }
+            '&' if next == Some('>') => {
+                let end = redirection_end(&chars, i + 1);
+                kinds[i..end].fill(TokenKind::Operator);
+                i = end;
+            }
+            '<' | '>' => {
+                let end = redirection_end(&chars, i);
+                kinds[i..end].fill(TokenKind::Operator);
+                i = end;
+            }
+            '\n' | ';' | '|' | '&' | '(' | ')' => {
+                let len = match (c, next) {
+                    ('|', Some('|')) | ('&', Some('&')) | (';', Some(';')) => 2,
+                    _ => 1,
+                };
+                kinds[i..i + len].fill(TokenKind::Operator);
+                i += len;
+                command_position = c != ')';
+            }
+            _ => {
+                let end = word_end(&chars, i);
+
+                // File descriptor redirections, such as `2>&1`.
+                if chars[i..end].iter().all(char::is_ascii_digit)
+                    && matches!(chars.get(end), Some('<' | '>'))
+                {
+                    let end = redirection_end(&chars, end);
+                    kinds[i..end].fill(TokenKind::Operator);
+                    i = end;
+                    continue;
+                }
+
+                let mut start = i;
+                if command_position {
+                    if let Some(equals) = assignment_length(&chars[i..end]) {
+                        // Assignments before a command, as in `FOO=bar make`.
+                        kinds[i..i + equals].fill(TokenKind::Variable);
+                        start = i + equals;
+                    } else {
+                        command_position = false;
+                        mark_word(&chars, &mut kinds, i, end, TokenKind::Command);
+                        i = end;
+                        continue;
+                    }
+                }
+
+                let kind = if c == '-' {
+                    TokenKind::Flag
+                } else {
+                    TokenKind::Plain
+                };
+                mark_word(&chars, &mut kinds, start, end, kind);
+                i = end;
+            }
+        }
+    }
+
+    kinds
+}
+
+/// The end of the redirection operator starting at `start`, such as `>`, `>>`, `<<<` or `>&2`.
+fn redirection_end(chars: &[char], start: usize) -> usize {
+    let mut end = start;
+    while end < chars.len() && end - start < 3 && matches!(chars[end], '<' | '>') {
+        end += 1;
+    }
+    if chars.get(end) == Some(&'&') {
+        end += 1;
+        while end < chars.len() && (chars[end].is_ascii_digit() || chars[end] == '-') {
+            end += 1;
+        }
+    }
+    end
+}
+
+/// The end of the word starting at `start`, skipping over quoted and escaped characters.
+fn word_end(chars: &[char], start: usize) -> usize {
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            ' ' | '\t' | '\n' | ';' | '|' | '&' | '(' | ')' | '<' | '>' => break,
+            '$' if chars.get(i + 1) == Some(&'(') => break,
+            '\\' => i += 2,
+            quote @ ('\'' | '"') => {
+                i += 1;
+                while i < chars.len() && chars[i] != quote {
+                    if quote == '"' && chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    i.min(chars.len())
+}
+
+/// The length of `NAME=` if `word` is a variable assignment.
+fn assignment_length(word: &[char]) -> Option<usize> {
+    let equals = word.iter().position(|c| *c == '=')?;
+    let name = &word[..equals];
+    let valid = name
+        .first()
+        .is_some_and(|c| c.is_ascii_alphabetic() || *c == '_')
+        && name.iter().all(|c| c.is_ascii_alphanumeric() || *c == '_');
+    valid.then_some(equals + 1)
+}
+
+/// Mark the chars of a word as `kind`, except for quoted strings and variables within it. The
+/// value of a flag such as `--color=auto` is left plain.
+fn mark_word(chars: &[char], kinds: &mut [TokenKind], start: usize, end: usize, kind: TokenKind) {
+    let mut kind = kind;
+    let mut i = start;
+    while i < end {
+        match chars[i] {
+            '\\' => {
+                let escape_end = (i + 2).min(end);
+                kinds[i..escape_end].fill(kind);
+                i = escape_end;
+            }
+            '\'' => {
+                let close = (i + 1..end).find(|j| chars[*j] == '\'').unwrap_or(end - 1);
+                kinds[i..=close].fill(TokenKind::String);
+                i = close + 1;
+            }
+            '"' => {
+                kinds[i] = TokenKind::String;
+                i += 1;
+                while i < end && chars[i] != '"' {
+                    if chars[i] == '$' {
+                        let variable_end = variable_end(chars, i, end);
+                        if variable_end > i + 1 {
+                            kinds[i..variable_end].fill(TokenKind::Variable);
+                            i = variable_end;
+                            continue;
+                        }
+                    }
+                    let escape_end = if chars[i] == '\\' { i + 2 } else { i + 1 }.min(end);
+                    kinds[i..escape_end].fill(TokenKind::String);
+                    i = escape_end;
+                }
+                if i < end {
+                    kinds[i] = TokenKind::String;
+                    i += 1;
+                }
+            }
+            '$' if variable_end(chars, i, end) > i + 1 => {
+                let variable_end = variable_end(chars, i, end);
+                kinds[i..variable_end].fill(TokenKind::Variable);
+                i = variable_end;
+            }
+            '=' if kind == TokenKind::Flag => {
+                kinds[i] = kind;
+                kind = TokenKind::Plain;
+                i += 1;
+            }
+            _ => {
+                kinds[i] = kind;
+                i += 1;
+            }
+        }
+    }
+}
+
+/// The end of a variable reference starting with the `$` at `start`, such as `$HOME`, `${HOME}`
+/// or `$?`. Returns `start + 1` if it isn't one.
+fn variable_end(chars: &[char], start: usize, end: usize) -> usize {
+    let mut i = start + 1;
+    match chars.get(i) {
+        Some('{') => {
+            while i < end && chars[i] != '}' {
+                i += 1;
+            }
+            (i + 1).min(end)
+        }
+        Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+            while i < end && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            i
+        }
+        Some(c) if c.is_ascii_digit() || matches!(c, '?' | '!' | '#' | '@' | '*' | '$' | '-') => {
+            (i + 1).min(end)
+        }
+        _ => i,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TokenKind, tokenize};
+
+    /// Render the kinds as one letter per char, to keep the expectations readable.
+    fn kinds(command: &str) -> String {
+        tokenize(command)
+            .into_iter()
+            .map(|kind| match kind {
+                TokenKind::Plain => '.',
+                TokenKind::Command => 'c',
+                TokenKind::Flag => 'f',
+                TokenKind::String => 's',
+                TokenKind::Variable => 'v',
+                TokenKind::Operator => 'o',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_pipelines() {
+        assert_eq!(
+            kinds("git log -n 5 | grep 'fix bug' > out.txt 2>&1"),
+            "ccc.....ff...o.cccc.sssssssss.o.........oooo"
+        );
+        assert_eq!(
+            kinds("make && FOO=1 ./run --color=auto; ls"),
+            "cccc.oo.vvvv..ccccc.ffffffff....o.cc"
+        );
+    }
+
+    #[test]
+    fn tokenize_variables_and_strings() {
+        assert_eq!(
+            kinds("echo \"$HOME/x\" ${USER} $(date)"),
+            "cccc.svvvvvsss.vvvvvvv.oocccco"
+        );
+        assert_eq!(kinds("echo a\\ b"), "cccc.....");
+    }
+}
@@ -0,0 +1,302 @@
+use crate::settings::{Colors, Palette, Settings};
+use config::Source;
+use crossterm::style::Color;
+
+/// The names of the built-in themes.
+pub const BUILTIN_THEMES: [&str; 4] = ["default", "solarized", "gruvbox", "high-contrast"];
+
+/// The colors of the theme called `name`. A `<name>.toml` in the themes directory takes
+/// precedence over a built-in theme of the same name. Theme files are laid out like the
+/// `[colors]` section of `config.toml`, and anything they leave out keeps its default color.
+#[must_use]
+pub fn load(name: &str) -> Option<Colors> {
+    let path = Settings::mcfly_themes_dir().join(format!("{name}.toml"));
+    if path.exists() {
+        let theme = config::File::from(path)
+            .collect()
+            .unwrap_or_else(|err| panic!("McFly error: Unable to read theme '{name}' ({err})"));
+        let mut colors = Colors::default();
+        colors.merge_config(theme);
+        return Some(colors);
+    }
+
+    builtin(name)
+}
+
+fn builtin(name: &str) -> Option<Colors> {
+    match name.to_lowercase().as_str() {
+        "default" => Some(Colors::default()),
+        "solarized" => Some(Colors {
+            menubar_bg: rgb(0x268bd2),
+            menubar_fg: rgb(0xfdf6e3),
+            darkmode_colors: Palette {
+                prompt: rgb(0x93a1a1),
+                timing: rgb(0x268bd2),
+                results_fg: rgb(0x839496),
+                results_bg: rgb(0x002b36),
+                results_hl: rgb(0xb58900),
+                results_selection_fg: rgb(0x93a1a1),
+                results_selection_bg: rgb(0x073642),
+                results_selection_hl: rgb(0xcb4b16),
+                syntax_command: rgb(0x859900),
+                syntax_flag: rgb(0x2aa198),
+                syntax_string: rgb(0xb58900),
+                syntax_variable: rgb(0xd33682),
+                syntax_operator: rgb(0xdc322f),
+            },
+            lightmode_colors: Palette {
+                prompt: rgb(0x586e75),
+                timing: rgb(0x268bd2),
+                results_fg: rgb(0x657b83),
+                results_bg: rgb(0xfdf6e3),
+                results_hl: rgb(0x268bd2),
+                results_selection_fg: rgb(0x586e75),
+                results_selection_bg: rgb(0xeee8d5),
+                results_selection_hl: rgb(0xcb4b16),
+                syntax_command: rgb(0x859900),
+                syntax_flag: rgb(0x2aa198),
+                syntax_string: rgb(0xb58900),
+                syntax_variable: rgb(0xd33682),
+                syntax_operator: rgb(0xdc322f),
+            },
+        }),
+        "gruvbox" => Some(Colors {
+            menubar_bg: rgb(0x504945),
+            menubar_fg: rgb(0xebdbb2),
+            darkmode_colors: Palette {
+                prompt: rgb(0xebdbb2),
+                timing: rgb(0x83a598),
+                results_fg: rgb(0xebdbb2),
+                results_bg: rgb(0x282828),
+                results_hl: rgb(0xfabd2f),
+                results_selection_fg: rgb(0xfbf1c7),
+                results_selection_bg: rgb(0x3c3836),
+                results_selection_hl: rgb(0xfe8019),
+                syntax_command: rgb(0xb8bb26),
+                syntax_flag: rgb(0x8ec07c),
+                syntax_string: rgb(0xfabd2f),
+                syntax_variable: rgb(0xd3869b),
+                syntax_operator: rgb(0xfb4934),
+            },
+            lightmode_colors: Palette {
+                prompt: rgb(0x3c3836),
+                timing: rgb(0x076678),
+                results_fg: rgb(0x3c3836),
+                results_bg: rgb(0xfbf1c7),
+                results_hl: rgb(0xb57614),
+                results_selection_fg: rgb(0x282828),
+                results_selection_bg: rgb(0xd5c4a1),
+                results_selection_hl: rgb(0xaf3a03),
+                syntax_command: rgb(0x79740e),
+                syntax_flag: rgb(0x427b58),
+                syntax_string: rgb(0xb57614),
+                syntax_variable: rgb(0x8f3f71),
+                syntax_operator: rgb(0x9d0006),
+            },
+        }),
+        // Plain ANSI colors, so that the terminal's own palette is respected.
+        "high-contrast" => Some(Colors {
+            menubar_bg: Color::White,
+            menubar_fg: Color::Black,
+            darkmode_colors: Palette {
+                prompt: Color::White,
+                timing: Color::Cyan,
+                results_fg: Color::White,
+                results_bg: Color::Black,
+                results_hl: Color::Yellow,
+                results_selection_fg: Color::Black,
+                results_selection_bg: Color::White,
+                results_selection_hl: Color::DarkBlue,
+                syntax_command: Color::Green,
+                syntax_flag: Color::Cyan,
+                syntax_string: Color::Yellow,
+                syntax_variable: Color::Magenta,
+                syntax_operator: Color::Red,
+            },
+            lightmode_colors: Palette {
+                prompt: Color::Black,
+                timing: Color::DarkBlue,
+                results_fg: Color::Black,
+                results_bg: Color::White,
+                results_hl: Color::DarkRed,
+                results_selection_fg: Color::White,
+                results_selection_bg: Color::Black,
+                results_selection_hl: Color::Yellow,
+                syntax_command: Color::DarkGreen,
+                syntax_flag: Color::DarkCyan,
+                syntax_string: Color::DarkYellow,
+                syntax_variable: Color::DarkMagenta,
+                syntax_operator: Color::DarkRed,
+            },
+        }),
+        _ => None,
+    }
+}
+
+const fn rgb(hex: u32) -> Color {
+    Color::Rgb {
+        r: (hex >> 16) as u8,
+        g: (hex >> 8) as u8,
+        b: hex as u8,
+    }
+}
+
+/// Parse a color name such as `blue` or `dark_grey`, an ANSI color number from 0 to 255, or a
+/// truecolor hex value such as `#268bd2` or `#28d`.
+#[must_use]
+pub fn parse_color(value: &str) -> Option<Color> {
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits = hex
+            .chars()
+            .map(|c| c.to_digit(16))
+            .collect::<Option<Vec<u32>>>()?;
+        let hex = match digits[..] {
+            [r, g, b] => ((r * 17) << 16) | ((g * 17) << 8) | (b * 17),
+            [_, _, _, _, _, _] => digits.iter().fold(0, |hex, digit| (hex << 4) | digit),
+            _ => return None,
+        };
+        return Some(rgb(hex));
+    }
+
+    if let Ok(ansi) = value.parse::<u8>() {
+        return Some(Color::AnsiValue(ansi));
+    }
+
+    Color::try_from(value).ok()
+}
+
+/// How long to wait for the terminal to report its background color.
+#[cfg(not(windows))]
+const BACKGROUND_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Ask the terminal for its background color with an OSC 11 query, and report whether it is
+/// light. Returns `None` if the terminal doesn't say.
+#[cfg(not(windows))]
+#[must_use]
+pub fn terminal_is_light() -> Option<bool> {
+    use crossterm::terminal;
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    terminal::enable_raw_mode().ok()?;
+
+    // Terminals that ignore OSC 11 still answer the device attributes query that follows it,
+    // so there is no need to wait for the timeout on them.
+    let mut response = Vec::new();
+    if tty
+        .write_all(b"\x1b]11;?\x07\x1b[c")
+        .and_then(|()| tty.flush())
+        .is_ok()
+    {
+        let deadline = Instant::now() + BACKGROUND_QUERY_TIMEOUT;
+        let mut buffer = [0; 64];
+        while !ends_with_device_attributes(&response) {
+            let timeout = deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis();
+            let mut poll_fd = libc::pollfd {
+                fd: tty.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = i32::try_from(timeout).unwrap_or(i32::MAX);
+            if timeout == 0 || unsafe { libc::poll(&mut poll_fd, 1, timeout) } <= 0 {
+                break;
+            }
+            match tty.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => response.extend_from_slice(&buffer[..read]),
+            }
+        }
+    }
+
+    let _ = terminal::disable_raw_mode();
+    parse_background(&String::from_utf8_lossy(&response)).map(is_light)
+}
+
+#[cfg(windows)]
+#[must_use]
+pub fn terminal_is_light() -> Option<bool> {
+    None
+}
+
+/// Whether `response` ends with a device attributes report, such as `\x1b[?62;c`.
+#[cfg(not(windows))]
+fn ends_with_device_attributes(response: &[u8]) -> bool {
+    response
+        .windows(3)
+        .rposition(|window| window == b"\x1b[?")
+        .is_some_and(|start| response[start..].ends_with(b"c"))
+}
+
+/// The background color in a reply to an OSC 11 query, such as `\x1b]11;rgb:2828/2828/2828\x07`,
+/// with each component scaled to between 0 and 1.
+fn parse_background(response: &str) -> Option<[f64; 3]> {
+    let start = response.find("]11;rgb:")? + "]11;rgb:".len();
+    let color = response[start..].split(['\x07', '\x1b']).next()?;
+
+    let mut components = [0.0; 3];
+    let mut parts = color.split('/');
+    for component in &mut components {
+        let part = parts.next()?;
+        if part.is_empty() || part.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(part, 16).ok()?;
+        let max = (1_u32 << (4 * part.len())) - 1;
+        *component = f64::from(value) / f64::from(max);
+    }
+    parts.next().is_none().then_some(components)
+}
+
+fn is_light([r, g, b]: [f64; 3]) -> bool {
+    0.2126 * r + 0.7152 * g + 0.0722 * b > 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_light, parse_background, parse_color};
+    use crossterm::style::Color;
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(
+            parse_color("#268bd2"),
+            Some(Color::Rgb {
+                r: 0x26,
+                g: 0x8b,
+                b: 0xd2
+            })
+        );
+        assert_eq!(
+            parse_color("#28d"),
+            Some(Color::Rgb {
+                r: 0x22,
+                g: 0x88,
+                b: 0xdd
+            })
+        );
+        assert_eq!(parse_color("208"), Some(Color::AnsiValue(208)));
+        assert_eq!(parse_color("dark_grey"), Some(Color::DarkGrey));
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("#gggggg"), None);
+        assert_eq!(parse_color("chartreuse"), None);
+    }
+
+    #[test]
+    fn parse_background_replies() {
+        let light = parse_background("\x1b]11;rgb:fdfd/f6f6/e3e3\x07\x1b[?62;c").unwrap();
+        assert!(is_light(light));
+        let dark = parse_background("\x1b]11;rgb:28/28/28\x1b\\").unwrap();
+        assert!(!is_light(dark));
+        assert_eq!(parse_background("\x1b[?62;c"), None);
+        assert_eq!(parse_background("\x1b]11;rgb:ffff/ffff\x07"), None);
+    }
+}
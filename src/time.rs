@@ -14,3 +14,56 @@ pub fn to_datetime(timestamp: i64) -> String {
     let utc = DateTime::from_timestamp(timestamp, 0).unwrap();
     Local.from_utc_datetime(&utc.naive_utc()).to_rfc3339()
 }
+
+/// Parse an age such as `90d`, `2y` or `1y6mo` into seconds. Units are `s`, `m` (minutes), `h`,
+/// `d`, `w`, `mo` (30 days) and `y` (365 days).
+pub fn parse_duration(s: &str) -> Result<i64, String> {
+    let invalid = || format!("invalid age '{s}', expected something like 90d or 2y");
+    let mut seconds: i64 = 0;
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit_end = rest[digits..]
+            .find(|c: char| c.is_ascii_digit())
+            .map_or(rest.len(), |i| digits + i);
+        let count: i64 = rest[..digits].parse().map_err(|_| invalid())?;
+        let unit = match &rest[digits..unit_end] {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            "w" => 7 * 24 * 60 * 60,
+            "mo" => 30 * 24 * 60 * 60,
+            "y" => 365 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        seconds = count
+            .checked_mul(unit)
+            .and_then(|part| seconds.checked_add(part))
+            .ok_or_else(invalid)?;
+        rest = &rest[unit_end..];
+    }
+    Ok(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_duration;
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("90d"), Ok(90 * 24 * 60 * 60));
+        assert_eq!(parse_duration("2y"), Ok(2 * 365 * 24 * 60 * 60));
+        assert_eq!(parse_duration("1y6mo"), Ok((365 + 6 * 30) * 24 * 60 * 60));
+        assert_eq!(parse_duration("45m"), Ok(45 * 60));
+        assert!(parse_duration("2").is_err());
+        assert!(parse_duration("y").is_err());
+        assert!(parse_duration("2 years").is_err());
+        assert!(parse_duration("").is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use crate::history::Command;
 use crate::history::Features;
 use crate::history::History;
-use crate::settings::{ResultFilter, Settings};
+use crate::settings::{FailureFilter, ResultFilter, Settings};
 use crate::training_cache;
 use rand::seq::IteratorRandom;
 use std::fs;
@@ -56,6 +56,7 @@ impl TrainingSampleGenerator {
             history.build_cache_table(
                 &command.dir.clone().unwrap(),
                 &ResultFilter::Global,
+                &FailureFilter::Show,
                 &Some(command.session_id.clone()),
                 None,
                 command.when_run,
@@ -64,7 +65,13 @@ impl TrainingSampleGenerator {
             );
 
             // Load the entire match set.
-            let results = history.find_matches("", -1, 0, &crate::settings::ResultSort::Rank);
+            let results = history.find_matches(
+                "",
+                -1,
+                0,
+                &crate::settings::CaseMode::Smart,
+                &crate::settings::ResultSort::Rank,
+            );
 
             // Get the features for this command at the time it was logged.
             if positive_examples <= negative_examples